@@ -0,0 +1,113 @@
+//! Mapping of account names to the real people behind them.
+//!
+//! The table lives in a TOML file (see `aliases.toml`) keyed by person, listing each of their accounts:
+//!
+//! ```toml
+//! Max = ["max", "PunishedMax", "OgreStreak"]
+//! ```
+//!
+//! The file is re-read whenever its modification time changes, so adding a new account doesn't need a restart.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Shown for accounts that don't belong to anyone in the table.
+pub const UNKNOWN_PERSON: &str = "?";
+
+#[derive(Default)]
+pub struct AliasTable {
+   /// Account name -> person.
+   people: HashMap<String, String>,
+   /// Person -> account names.
+   accounts: BTreeMap<String, Vec<String>>,
+}
+
+impl AliasTable {
+   fn parse(contents: &str) -> Result<AliasTable, toml::de::Error> {
+      let by_person: BTreeMap<String, Vec<String>> = toml::from_str(contents)?;
+      let mut people = HashMap::new();
+      for (person, accounts) in &by_person {
+         for account in accounts {
+            people.insert(account.clone(), person.clone());
+         }
+      }
+      Ok(AliasTable {
+         people: people,
+         accounts: by_person,
+      })
+   }
+
+   pub fn real_name(&self, account: &str) -> &str {
+      self.people.get(account).map(|x| x.as_str()).unwrap_or(UNKNOWN_PERSON)
+   }
+
+   /// Every account belonging to `person`, or `None` if we don't know of them.
+   pub fn accounts(&self, person: &str) -> Option<&[String]> {
+      self.accounts.get(person).map(|x| x.as_slice())
+   }
+}
+
+pub struct Aliases {
+   path: PathBuf,
+   current: RwLock<(Option<SystemTime>, Arc<AliasTable>)>,
+}
+
+impl Aliases {
+   pub fn load(path: PathBuf) -> Aliases {
+      let aliases = Aliases {
+         path: path,
+         current: RwLock::new((None, Arc::new(AliasTable::default()))),
+      };
+      if !aliases.path.exists() {
+         warn!(
+            "Alias file {} doesn't exist, every player will be shown as \"{}\"",
+            aliases.path.display(),
+            UNKNOWN_PERSON
+         );
+      }
+      aliases.reload_if_changed();
+      aliases
+   }
+
+   /// The alias table, re-read from disk first if the file has changed since it was last loaded.
+   pub fn current(&self) -> Arc<AliasTable> {
+      self.reload_if_changed();
+      self.current.read().unwrap().1.clone()
+   }
+
+   fn reload_if_changed(&self) {
+      let modified = match fs::metadata(&self.path).and_then(|x| x.modified()) {
+         Ok(modified) => modified,
+         Err(_) => return,
+      };
+      if self.current.read().unwrap().0 == Some(modified) {
+         return;
+      }
+      let mut current = self.current.write().unwrap();
+      if current.0 == Some(modified) {
+         // Another request got here first
+         return;
+      }
+      // Don't retry a broken file until it changes again
+      current.0 = Some(modified);
+      let table = fs::read_to_string(&self.path)
+         .map_err(|e| e.to_string())
+         .and_then(|x| AliasTable::parse(&x).map_err(|e| e.to_string()));
+      match table {
+         Ok(table) => {
+            info!("Loaded {} aliases from {}", table.people.len(), self.path.display());
+            current.1 = Arc::new(table);
+         }
+         Err(e) => {
+            error!(
+               "Couldn't load alias file {}, keeping the old aliases: {}",
+               self.path.display(),
+               e
+            );
+         }
+      }
+   }
+}
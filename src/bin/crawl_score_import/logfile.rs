@@ -0,0 +1,226 @@
+//! Parsing of DCSS `logfile` lines, one per finished game, and `milestones` lines, one per notable event during a
+//! game.
+//!
+//! Each line is a list of `key=value` fields separated by `:`. A literal colon inside a value is written as `::`.
+
+use crawl_model::data::{Background, God, Species};
+use std::collections::HashMap;
+
+/// A finished game, ready to be inserted into the `games` table.
+pub struct LogfileGame {
+   pub name: String,
+   pub species_id: i64,
+   pub background_id: i64,
+   pub god_id: i64,
+   pub xl: i64,
+   pub place: String,
+   pub tmsg: String,
+   pub turn: i64,
+   pub score: i64,
+   pub runes: i64,
+   pub dur: i64,
+   /// Unix timestamp.
+   pub end: i64,
+}
+
+/// Something that happened during a game, ready to be inserted into the `milestones` table.
+pub struct LogfileMilestone {
+   pub name: String,
+   /// When the game started, as a unix timestamp. Together with `name` this identifies the game.
+   pub start: i64,
+   pub species_id: i64,
+   pub background_id: i64,
+   pub god_id: i64,
+   pub xl: i64,
+   pub place: String,
+   pub turn: i64,
+   pub runes: i64,
+   /// When the milestone happened, as a unix timestamp.
+   pub time: i64,
+   /// The milestone type, e.g. `rune`, `god.worship`, `uniq` or `br.enter`.
+   pub kind: String,
+   /// e.g. `found a slimy rune of Zot.`
+   pub message: String,
+}
+
+struct Fields(HashMap<String, String>);
+
+impl Fields {
+   fn text(&self, key: &str) -> Result<&str, String> {
+      self
+         .0
+         .get(key)
+         .map(|x| x.as_str())
+         .ok_or_else(|| format!("missing `{}`", key))
+   }
+
+   fn number(&self, key: &str) -> Result<i64, String> {
+      self.text(key)?.parse().map_err(|_| format!("`{}` isn't a number", key))
+   }
+
+   /// Runes are only recorded once the player has some.
+   fn runes(&self) -> Result<i64, String> {
+      if self.0.contains_key("urune") {
+         self.number("urune")
+      } else {
+         Ok(0)
+      }
+   }
+
+   fn god_id(&self) -> Result<i64, String> {
+      Ok(parse_god(self.0.get("god").map(|x| x.as_str()).unwrap_or(""))? as i64)
+   }
+}
+
+/// Splits a line into its fields, undoing the `::` escaping.
+fn parse_fields(line: &str) -> Fields {
+   let mut fields = HashMap::new();
+   let mut field = String::new();
+   let mut chars = line.trim_end_matches(|x| x == '\n' || x == '\r').chars().peekable();
+   loop {
+      match chars.next() {
+         Some(':') if chars.peek() == Some(&':') => {
+            chars.next();
+            field.push(':');
+         }
+         Some(':') | None => {
+            if let Some(split) = field.find('=') {
+               fields.insert(field[..split].to_string(), field[split + 1..].to_string());
+            }
+            field.clear();
+            if chars.peek().is_none() {
+               break;
+            }
+         }
+         Some(c) => field.push(c),
+      }
+   }
+   Fields(fields)
+}
+
+pub fn parse_game(line: &str) -> Result<LogfileGame, String> {
+   let fields = parse_fields(line);
+   Ok(LogfileGame {
+      name: fields.text("name")?.to_string(),
+      species_id: parse_species(fields.text("race")?)? as i64,
+      background_id: parse_background(fields.text("cls")?)? as i64,
+      god_id: fields.god_id()?,
+      xl: fields.number("xl")?,
+      place: fields.text("place")?.to_string(),
+      tmsg: fields.text("tmsg")?.to_string(),
+      turn: fields.number("turn")?,
+      score: fields.number("sc")?,
+      runes: fields.runes()?,
+      dur: fields.number("dur")?,
+      end: parse_time(fields.text("end")?)?,
+   })
+}
+
+pub fn parse_milestone(line: &str) -> Result<LogfileMilestone, String> {
+   let fields = parse_fields(line);
+   Ok(LogfileMilestone {
+      name: fields.text("name")?.to_string(),
+      start: parse_time(fields.text("start")?)?,
+      species_id: parse_species(fields.text("race")?)? as i64,
+      background_id: parse_background(fields.text("cls")?)? as i64,
+      god_id: fields.god_id()?,
+      xl: fields.number("xl")?,
+      place: fields.text("place")?.to_string(),
+      turn: fields.number("turn")?,
+      runes: fields.runes()?,
+      time: parse_time(fields.text("time")?)?,
+      kind: fields.text("type")?.to_string(),
+      message: fields.text("milestone")?.to_string(),
+   })
+}
+
+/// Logfile names are spaced out (`High Elf`), the enum variants aren't (`HighElf`).
+fn parse_name<T: std::str::FromStr>(kind: &str, name: &str) -> Result<T, String> {
+   name
+      .replace(' ', "")
+      .parse()
+      .map_err(|_| format!("unknown {} `{}`", kind, name))
+}
+
+fn parse_species(name: &str) -> Result<Species, String> {
+   // Draconians are recorded by colour, e.g. `Red Draconian`
+   if name.ends_with("Draconian") {
+      return Ok(Species::Draconian);
+   }
+   parse_name("species", name)
+}
+
+fn parse_background(name: &str) -> Result<Background, String> {
+   parse_name("background", name)
+}
+
+fn parse_god(name: &str) -> Result<God, String> {
+   match name {
+      "" | "No God" => Ok(God::Atheist),
+      "the Shining One" => Ok(God::TheShiningOne),
+      "Nemelex Xobeh" => Ok(God::Nemelex),
+      _ => parse_name("god", name),
+   }
+}
+
+/// Parses a time such as `20180501213004S` (the trailing `S` or `D` marks daylight saving) into a unix timestamp.
+/// The times are in UTC, and the month counts from zero.
+fn parse_time(time: &str) -> Result<i64, String> {
+   let invalid = || format!("invalid time `{}`", time);
+   if time.len() < 14 || !time.is_ascii() {
+      return Err(invalid());
+   }
+   let part = |start: usize, end: usize| time[start..end].parse::<u32>().map_err(|_| invalid());
+   let (year, month, day) = (part(0, 4)?, part(4, 6)?, part(6, 8)?);
+   let (hour, minute, second) = (part(8, 10)?, part(10, 12)?, part(12, 14)?);
+   chrono::NaiveDate::from_ymd_opt(year as i32, month + 1, day)
+      .and_then(|x| x.and_hms_opt(hour, minute, second))
+      .map(|x| x.timestamp())
+      .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn escaped_colons_stay_in_the_value() {
+      let fields = parse_fields("name=brick:tmsg=slain by a:: goblin:place=D::3");
+      assert_eq!(fields.0["name"], "brick");
+      assert_eq!(fields.0["tmsg"], "slain by a: goblin");
+      assert_eq!(fields.0["place"], "D:3");
+   }
+
+   #[test]
+   fn trailing_escaped_colon() {
+      let fields = parse_fields("name=brick:killer=a::\n");
+      assert_eq!(fields.0["name"], "brick");
+      assert_eq!(fields.0["killer"], "a:");
+   }
+
+   #[test]
+   fn fields_without_equals_are_ignored() {
+      let fields = parse_fields("junk:name=brick:xl=3");
+      assert_eq!(fields.0.len(), 2);
+      assert_eq!(fields.0["name"], "brick");
+      assert_eq!(fields.0["xl"], "3");
+      assert!(!fields.0.contains_key("junk"));
+   }
+
+   #[test]
+   fn months_count_from_zero() {
+      // 2018-01-01 00:00:00 UTC
+      assert_eq!(parse_time("20180001000000S"), Ok(1_514_764_800));
+      // 2018-12-31 23:59:59 UTC
+      assert_eq!(parse_time("20181131235959D"), Ok(1_546_300_799));
+      assert!(parse_time("20181201000000S").is_err());
+   }
+
+   #[test]
+   fn malformed_times_are_rejected() {
+      assert!(parse_time("").is_err());
+      assert!(parse_time("2018").is_err());
+      assert!(parse_time("2018000100000éS").is_err());
+      assert!(parse_time("201é000100000S").is_err());
+   }
+}
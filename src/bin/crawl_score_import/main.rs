@@ -0,0 +1,189 @@
+//! Imports finished games from DCSS `logfile`s into the `games` table read by the server, and events from
+//! `milestones` files into the `milestones` table.
+//!
+//! Usage: `crawl_score_import [--watch] [--milestones <milestones>]... <logfile>...`, with the database given by
+//! `DATABASE_URL` as for the server.
+//!
+//! Only lines added since the previous run are imported. With `--watch` the files are checked again every couple of
+//! seconds until the process is killed, so it can be left running next to the server to keep the hiscores current.
+
+extern crate chrono;
+extern crate crawl_model;
+#[macro_use]
+extern crate diesel;
+extern crate dotenv;
+
+mod logfile;
+#[path = "../../schema.rs"]
+mod schema;
+mod tail;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use dotenv::dotenv;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// How long `--watch` waits between checks for new lines.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Games already in the table, e.g. from a logfile that was replaced by a copy of itself, aren't inserted again. The
+/// `games` table isn't ours to add a unique index to, so that's checked here.
+fn insert_game(connection: &SqliteConnection, game: &logfile::LogfileGame) -> QueryResult<usize> {
+   use crawl_model::db_schema::games::dsl::*;
+   let existing: i64 = games
+      .filter(name.eq(&game.name))
+      .filter(end.eq(game.end))
+      .filter(score.eq(game.score))
+      .count()
+      .get_result(connection)?;
+   if existing > 0 {
+      return Ok(0);
+   }
+   diesel::insert_into(games)
+      .values((
+         name.eq(&game.name),
+         species_id.eq(game.species_id),
+         background_id.eq(game.background_id),
+         god_id.eq(game.god_id),
+         xl.eq(game.xl),
+         place.eq(&game.place),
+         tmsg.eq(&game.tmsg),
+         turn.eq(game.turn),
+         score.eq(game.score),
+         runes.eq(game.runes),
+         dur.eq(game.dur),
+         end.eq(game.end),
+      ))
+      .execute(connection)
+}
+
+fn insert_milestone(connection: &SqliteConnection, milestone: &logfile::LogfileMilestone) -> QueryResult<usize> {
+   use schema::milestones::dsl::*;
+   diesel::insert_or_ignore_into(milestones)
+      .values((
+         name.eq(&milestone.name),
+         start.eq(milestone.start),
+         species_id.eq(milestone.species_id),
+         background_id.eq(milestone.background_id),
+         god_id.eq(milestone.god_id),
+         xl.eq(milestone.xl),
+         place.eq(&milestone.place),
+         turn.eq(milestone.turn),
+         runes.eq(milestone.runes),
+         time.eq(milestone.time),
+         kind.eq(&milestone.kind),
+         message.eq(&milestone.message),
+      ))
+      .execute(connection)
+}
+
+fn create_milestones_table(connection: &SqliteConnection) -> QueryResult<()> {
+   connection.execute(
+      "CREATE TABLE IF NOT EXISTS milestones (\
+       id INTEGER PRIMARY KEY NOT NULL, \
+       name TEXT NOT NULL, \
+       start BIGINT NOT NULL, \
+       species_id BIGINT NOT NULL, \
+       background_id BIGINT NOT NULL, \
+       god_id BIGINT NOT NULL, \
+       xl BIGINT NOT NULL, \
+       place TEXT NOT NULL, \
+       turn BIGINT NOT NULL, \
+       runes BIGINT NOT NULL, \
+       time BIGINT NOT NULL, \
+       kind TEXT NOT NULL, \
+       message TEXT NOT NULL)",
+   )?;
+   // Older versions could import the same milestones twice, which have to go before the unique index can be added
+   connection.execute(
+      "DELETE FROM milestones WHERE id NOT IN (SELECT MIN(id) FROM milestones GROUP BY name, start, time, kind)",
+   )?;
+   connection.execute("DROP INDEX IF EXISTS milestones_game")?;
+   connection.execute("CREATE UNIQUE INDEX IF NOT EXISTS milestones_unique ON milestones (name, start, time, kind)")?;
+   Ok(())
+}
+
+enum Source {
+   Logfile(PathBuf),
+   Milestones(PathBuf),
+}
+
+impl Source {
+   fn path(&self) -> &Path {
+      match *self {
+         Source::Logfile(ref path) | Source::Milestones(ref path) => path,
+      }
+   }
+
+   fn import_new_lines(&self, connection: &SqliteConnection) -> Result<tail::Progress, tail::Error> {
+      match *self {
+         Source::Logfile(ref path) => tail::import_new_lines(connection, path, logfile::parse_game, insert_game),
+         Source::Milestones(ref path) => {
+            tail::import_new_lines(connection, path, logfile::parse_milestone, insert_milestone)
+         }
+      }
+   }
+
+   fn noun(&self) -> &'static str {
+      match *self {
+         Source::Logfile(_) => "games",
+         Source::Milestones(_) => "milestones",
+      }
+   }
+}
+
+fn usage() -> ! {
+   eprintln!("Usage: crawl_score_import [--watch] [--milestones <milestones>]... <logfile>...");
+   process::exit(2);
+}
+
+fn main() {
+   dotenv().ok();
+
+   let mut watch = false;
+   let mut sources = vec![];
+   let mut args = std::env::args().skip(1);
+   while let Some(arg) = args.next() {
+      match arg.as_str() {
+         "--watch" => watch = true,
+         "--milestones" => sources.push(Source::Milestones(args.next().unwrap_or_else(|| usage()).into())),
+         _ if arg.starts_with("--") => usage(),
+         _ => sources.push(Source::Logfile(arg.into())),
+      }
+   }
+   if sources.is_empty() {
+      usage();
+   }
+   let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+   let connection = SqliteConnection::establish(&database_url).expect("Failed to connect to the database.");
+   tail::create_offsets_table(&connection).expect("Failed to create the logfile_offsets table.");
+   create_milestones_table(&connection).expect("Failed to create the milestones table.");
+
+   loop {
+      let mut failed = false;
+      for source in &sources {
+         match source.import_new_lines(&connection) {
+            Ok(ref progress) if watch && progress.is_empty() => (),
+            Ok(progress) => println!(
+               "Imported {} {} from {}, skipped {} and {} already imported",
+               progress.imported,
+               source.noun(),
+               source.path().display(),
+               progress.skipped,
+               progress.duplicates
+            ),
+            Err(e) => {
+               eprintln!("Couldn't import {}, nothing was saved: {}", source.path().display(), e);
+               failed = true;
+            }
+         }
+      }
+      if !watch {
+         process::exit(if failed { 1 } else { 0 });
+      }
+      thread::sleep(WATCH_INTERVAL);
+   }
+}
@@ -0,0 +1,231 @@
+//! Incremental reading of append-only DCSS files such as `logfile`.
+//!
+//! How far we've read into each file is kept in the `logfile_offsets` table, so every run only imports the lines
+//! added since the last one. If a file shrinks or its first line changes, it has been truncated or replaced, and is
+//! read again from the start.
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// `byte_offset` is where the next unread line starts, and `first_line` is kept to notice when the file has been
+// replaced by a different one.
+table! {
+   logfile_offsets (source) {
+      source -> Text,
+      byte_offset -> BigInt,
+      first_line -> Text,
+   }
+}
+
+pub fn create_offsets_table(connection: &SqliteConnection) -> QueryResult<()> {
+   connection.execute(
+      "CREATE TABLE IF NOT EXISTS logfile_offsets (\
+       source TEXT PRIMARY KEY NOT NULL, \
+       byte_offset BIGINT NOT NULL, \
+       first_line TEXT NOT NULL)",
+   )?;
+   Ok(())
+}
+
+#[derive(Debug)]
+pub enum Error {
+   Io(io::Error),
+   Database(diesel::result::Error),
+}
+
+impl From<io::Error> for Error {
+   fn from(e: io::Error) -> Error {
+      Error::Io(e)
+   }
+}
+
+impl From<diesel::result::Error> for Error {
+   fn from(e: diesel::result::Error) -> Error {
+      Error::Database(e)
+   }
+}
+
+impl fmt::Display for Error {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match *self {
+         Error::Io(ref e) => write!(f, "couldn't read file: {}", e),
+         Error::Database(ref e) => write!(f, "database error: {}", e),
+      }
+   }
+}
+
+#[derive(Default)]
+pub struct Progress {
+   pub imported: usize,
+   pub skipped: usize,
+   pub duplicates: usize,
+}
+
+impl Progress {
+   /// Whether there were no new lines at all.
+   pub fn is_empty(&self) -> bool {
+      self.imported == 0 && self.skipped == 0 && self.duplicates == 0
+   }
+}
+
+/// Parses and inserts every complete line added to `path` since it was last read. Lines that fail to parse are
+/// reported and skipped. `insert` returns 0 for items that were already imported, e.g. when a replaced file is read
+/// again from the start. Either all of the new lines are imported and the offset moves past them, or none are.
+pub fn import_new_lines<T>(
+   connection: &SqliteConnection,
+   path: &Path,
+   parse: fn(&str) -> Result<T, String>,
+   insert: fn(&SqliteConnection, &T) -> QueryResult<usize>,
+) -> Result<Progress, Error> {
+   let source = path.canonicalize()?.to_string_lossy().into_owned();
+   let mut file = File::open(path)?;
+   let mut first_line = String::new();
+   BufReader::new(&file).read_line(&mut first_line)?;
+   let len = file.metadata()?.len();
+   let saved: Option<(i64, String)> = logfile_offsets::table
+      .filter(logfile_offsets::source.eq(&source))
+      .select((logfile_offsets::byte_offset, logfile_offsets::first_line))
+      .first(connection)
+      .optional()?;
+   let start = match saved {
+      Some((offset, ref saved_first_line)) if offset as u64 <= len && *saved_first_line == first_line => offset as u64,
+      Some((offset, _)) if offset > 0 => {
+         eprintln!("{} has been truncated or replaced, importing it from the start", source);
+         0
+      }
+      _ => 0,
+   };
+
+   file.seek(SeekFrom::Start(start))?;
+   let mut new = Vec::new();
+   file.read_to_end(&mut new)?;
+   // Anything after the last newline is a line that's still being written
+   let complete = new.iter().rposition(|&x| x == b'\n').map(|x| x + 1).unwrap_or(0);
+
+   let mut progress = Progress::default();
+   connection.transaction::<_, Error, _>(|| {
+      for line in new[..complete].split(|&x| x == b'\n') {
+         let line = String::from_utf8_lossy(line);
+         if line.trim().is_empty() {
+            continue;
+         }
+         match parse(&line) {
+            Ok(item) => {
+               if insert(connection, &item)? > 0 {
+                  progress.imported += 1;
+               } else {
+                  progress.duplicates += 1;
+               }
+            }
+            Err(e) => {
+               eprintln!("{}: skipping line, {}", source, e);
+               progress.skipped += 1;
+            }
+         }
+      }
+      diesel::replace_into(logfile_offsets::table)
+         .values((
+            logfile_offsets::source.eq(&source),
+            logfile_offsets::byte_offset.eq((start + complete as u64) as i64),
+            logfile_offsets::first_line.eq(&first_line),
+         ))
+         .execute(connection)?;
+      Ok(())
+   })?;
+   Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::fs::{self, OpenOptions};
+   use std::io::Write;
+   use std::path::PathBuf;
+
+   table! {
+      test_lines (line) {
+         line -> Text,
+      }
+   }
+
+   fn parse(line: &str) -> Result<String, String> {
+      Ok(line.into())
+   }
+
+   #[allow(clippy::ptr_arg)]
+   fn insert(connection: &SqliteConnection, line: &String) -> QueryResult<usize> {
+      diesel::insert_or_ignore_into(test_lines::table)
+         .values(test_lines::line.eq(line))
+         .execute(connection)
+   }
+
+   fn setup(name: &str) -> (SqliteConnection, PathBuf) {
+      let connection = SqliteConnection::establish(":memory:").unwrap();
+      create_offsets_table(&connection).unwrap();
+      connection
+         .execute("CREATE TABLE test_lines (line TEXT PRIMARY KEY NOT NULL)")
+         .unwrap();
+      let path = std::env::temp_dir().join(format!("crawl_score_import_{}_{}", name, std::process::id()));
+      let _ = fs::remove_file(&path);
+      (connection, path)
+   }
+
+   fn append(path: &Path, text: &str) {
+      OpenOptions::new()
+         .create(true)
+         .append(true)
+         .open(path)
+         .unwrap()
+         .write_all(text.as_bytes())
+         .unwrap();
+   }
+
+   fn import(connection: &SqliteConnection, path: &Path) -> (usize, usize) {
+      let progress = import_new_lines(connection, path, parse, insert).unwrap();
+      (progress.imported, progress.duplicates)
+   }
+
+   fn lines(connection: &SqliteConnection) -> Vec<String> {
+      test_lines::table.order(test_lines::line).load(connection).unwrap()
+   }
+
+   #[test]
+   fn partial_lines_wait_until_finished() {
+      let (connection, path) = setup("partial");
+      append(&path, "a\nb\npar");
+      assert_eq!(import(&connection, &path), (2, 0));
+      append(&path, "tial\nc\n");
+      assert_eq!(import(&connection, &path), (2, 0));
+      assert_eq!(import(&connection, &path), (0, 0));
+      fs::remove_file(&path).unwrap();
+      assert_eq!(lines(&connection), vec!["a", "b", "c", "partial"]);
+   }
+
+   #[test]
+   fn shrunk_files_are_read_again_without_duplicates() {
+      let (connection, path) = setup("shrunk");
+      append(&path, "a\nb\nc\n");
+      assert_eq!(import(&connection, &path), (3, 0));
+      fs::write(&path, "a\n").unwrap();
+      assert_eq!(import(&connection, &path), (0, 1));
+      append(&path, "d\n");
+      assert_eq!(import(&connection, &path), (1, 0));
+      fs::remove_file(&path).unwrap();
+      assert_eq!(lines(&connection), vec!["a", "b", "c", "d"]);
+   }
+
+   #[test]
+   fn replaced_files_are_read_again() {
+      let (connection, path) = setup("replaced");
+      append(&path, "a\nb\n");
+      assert_eq!(import(&connection, &path), (2, 0));
+      fs::write(&path, "x\ny\nz\n").unwrap();
+      assert_eq!(import(&connection, &path), (3, 0));
+      fs::remove_file(&path).unwrap();
+      assert_eq!(lines(&connection), vec!["a", "b", "x", "y", "z"]);
+   }
+}
@@ -0,0 +1,151 @@
+//! Parsing of the `since`/`until` query parameters and formatting of game end times.
+//!
+//! Game end times are stored in the `games` table as unix timestamps (UTC).
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+
+/// The furthest back a relative date can reach, so that it stays well within what chrono can represent.
+const MAX_RELATIVE_DAYS: i64 = 1_000_000;
+
+/// A point in time given in a query, either an ISO date (`2018-06-01`) or an amount of time before now (`7d`).
+#[derive(Clone, Copy, Debug)]
+pub enum DateParam {
+   Absolute(NaiveDate),
+   Relative(Duration),
+}
+
+impl DateParam {
+   /// The earliest timestamp covered by this date.
+   pub fn start_timestamp(&self) -> i64 {
+      match *self {
+         DateParam::Absolute(date) => date.and_hms(0, 0, 0).timestamp(),
+         DateParam::Relative(ago) => relative_timestamp(ago).unwrap_or(0),
+      }
+   }
+
+   /// The latest timestamp covered by this date. An absolute date includes the whole day.
+   pub fn end_timestamp(&self) -> i64 {
+      match *self {
+         DateParam::Absolute(date) => date.and_hms(23, 59, 59).timestamp(),
+         DateParam::Relative(ago) => relative_timestamp(ago).unwrap_or(0),
+      }
+   }
+}
+
+/// The timestamp `ago` before now, or `None` if that's out of range. Parsing rejects any such duration.
+fn relative_timestamp(ago: Duration) -> Option<i64> {
+   Utc::now().checked_sub_signed(ago).map(|x| x.timestamp())
+}
+
+impl std::fmt::Display for DateParam {
+   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+      match *self {
+         DateParam::Absolute(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+         DateParam::Relative(ago) => write!(f, "{} days ago", ago.num_days()),
+      }
+   }
+}
+
+impl std::str::FromStr for DateParam {
+   type Err = ();
+
+   fn from_str(s: &str) -> Result<DateParam, ()> {
+      if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+         return Ok(DateParam::Absolute(date));
+      }
+      let (split, unit) = s.char_indices().last().ok_or(())?;
+      let amount: i64 = s[..split].parse().map_err(|_| ())?;
+      if amount < 0 {
+         return Err(());
+      }
+      let days = match unit {
+         'd' => Some(amount),
+         'w' => amount.checked_mul(7),
+         'y' => amount.checked_mul(365),
+         _ => None,
+      };
+      let days = days.filter(|&x| x <= MAX_RELATIVE_DAYS).ok_or(())?;
+      let ago = Duration::days(days);
+      relative_timestamp(ago).ok_or(())?;
+      Ok(DateParam::Relative(ago))
+   }
+}
+
+impl<'a> rocket::request::FromFormValue<'a> for DateParam {
+   type Error = ();
+   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<DateParam, ()> {
+      param.percent_decode_lossy().parse()
+   }
+}
+
+/// Formats a game end time for display, e.g. `2018-06-01 21:04`. Out of range timestamps are shown as is.
+pub fn format_timestamp(timestamp: i64) -> String {
+   match NaiveDateTime::from_timestamp_opt(timestamp, 0) {
+      Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+      None => timestamp.to_string(),
+   }
+}
+
+/// How long ago `timestamp` was, roughly, e.g. `3 hours ago`.
+pub fn format_age(timestamp: i64) -> String {
+   let seconds = Utc::now().timestamp().saturating_sub(timestamp);
+   if seconds < 60 {
+      return "just now".into();
+   }
+   let (amount, unit) = if seconds < 60 * 60 {
+      (seconds / 60, "minute")
+   } else if seconds < 24 * 60 * 60 {
+      (seconds / (60 * 60), "hour")
+   } else {
+      (seconds / (24 * 60 * 60), "day")
+   };
+   format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn absolute_dates() {
+      match "2018-06-01".parse::<DateParam>() {
+         Ok(DateParam::Absolute(date)) => assert_eq!(date, NaiveDate::from_ymd(2018, 6, 1)),
+         x => panic!("unexpected {:?}", x),
+      }
+   }
+
+   #[test]
+   fn relative_dates() {
+      for &(input, days) in &[("7d", 7), ("2w", 14), ("1y", 365), ("0d", 0)] {
+         match input.parse::<DateParam>() {
+            Ok(DateParam::Relative(ago)) => assert_eq!(ago, Duration::days(days)),
+            x => panic!("unexpected {:?} for {}", x, input),
+         }
+      }
+   }
+
+   #[test]
+   fn malformed_dates_are_rejected() {
+      for input in &["", "d", "7", "7x", "-1d", "1é", "é", "2018-13-01"] {
+         assert!(input.parse::<DateParam>().is_err(), "{} should be rejected", input);
+      }
+   }
+
+   #[test]
+   fn out_of_range_dates_are_rejected() {
+      for input in &[
+         "100000000d",
+         "9223372036854775807d",
+         "9223372036854775807w",
+         "9223372036854775807y",
+      ] {
+         assert!(input.parse::<DateParam>().is_err(), "{} should be rejected", input);
+      }
+   }
+
+   #[test]
+   fn out_of_range_timestamps_are_shown_as_is() {
+      assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+      assert_eq!(format_timestamp(std::i64::MAX), std::i64::MAX.to_string());
+   }
+}
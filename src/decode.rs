@@ -0,0 +1,250 @@
+//! Checked conversion of the species, background and god IDs stored in the `games` table back into
+//! `crawl_model::data` enums.
+//!
+//! The database may be filled by a newer version of the importer than this server was built against, so an ID
+//! is never assumed to be valid. Anything we don't recognize decodes to `Decoded::Unknown` and is logged.
+
+use crawl_model::data::{Background, God, Species};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decoded<T> {
+   Known(T),
+   Unknown(i64),
+}
+
+impl<T> Decoded<T> {
+   pub fn known(self) -> Option<T> {
+      match self {
+         Decoded::Known(x) => Some(x),
+         Decoded::Unknown(_) => None,
+      }
+   }
+}
+
+impl<T: fmt::Debug> fmt::Display for Decoded<T> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match *self {
+         Decoded::Known(ref x) => write!(f, "{:?}", x),
+         Decoded::Unknown(id) => write!(f, "Unknown({})", id),
+      }
+   }
+}
+
+pub trait DecodeId: Copy + 'static {
+   /// Human readable name of the column, used when logging unknown IDs.
+   const KIND: &'static str;
+   /// Every variant of the enum. Must be kept in sync with `crawl_model::data`.
+   const VARIANTS: &'static [Self];
+
+   fn id(self) -> i64;
+
+   fn decode(id: i64) -> Decoded<Self> {
+      match Self::VARIANTS.iter().find(|x| x.id() == id) {
+         Some(x) => Decoded::Known(*x),
+         None => {
+            warn!("Unknown {} id {} in games table", Self::KIND, id);
+            Decoded::Unknown(id)
+         }
+      }
+   }
+}
+
+/// An SQL expression giving the name of the variant stored in `column`, so that results can be sorted by name
+/// rather than by ID. Unknown IDs come out as NULL.
+pub fn name_sql<T: DecodeId + fmt::Debug>(column: &str) -> String {
+   let mut sql = format!("CASE {}", column);
+   for variant in T::VARIANTS {
+      sql.push_str(&format!(" WHEN {} THEN '{:?}'", variant.id(), variant));
+   }
+   sql.push_str(" END");
+   sql
+}
+
+impl DecodeId for Species {
+   const KIND: &'static str = "species";
+   const VARIANTS: &'static [Species] = &[
+      Species::Human,
+      Species::HighElf,
+      Species::DeepElf,
+      Species::Halfling,
+      Species::HillOrc,
+      Species::Kobold,
+      Species::Mummy,
+      Species::Naga,
+      Species::Ogre,
+      Species::Troll,
+      Species::Draconian,
+      Species::Centaur,
+      Species::Demigod,
+      Species::Spriggan,
+      Species::Minotaur,
+      Species::Demonspawn,
+      Species::Ghoul,
+      Species::Tengu,
+      Species::Merfolk,
+      Species::Vampire,
+      Species::DeepDwarf,
+      Species::Felid,
+      Species::Octopode,
+      Species::Gargoyle,
+      Species::Formicid,
+      Species::VineStalker,
+      Species::Barachi,
+      Species::Gnoll,
+   ];
+
+   fn id(self) -> i64 {
+      self as i64
+   }
+}
+
+impl DecodeId for Background {
+   const KIND: &'static str = "background";
+   const VARIANTS: &'static [Background] = &[
+      Background::Fighter,
+      Background::Wizard,
+      Background::Priest,
+      Background::Gladiator,
+      Background::Necromancer,
+      Background::Assassin,
+      Background::Berserker,
+      Background::Hunter,
+      Background::Conjurer,
+      Background::Enchanter,
+      Background::FireElementalist,
+      Background::IceElementalist,
+      Background::Summoner,
+      Background::AirElementalist,
+      Background::EarthElementalist,
+      Background::Skald,
+      Background::VenomMage,
+      Background::ChaosKnight,
+      Background::Transmuter,
+      Background::Monk,
+      Background::Warper,
+      Background::Wanderer,
+      Background::ArcaneMarksman,
+      Background::AbyssalKnight,
+      Background::Artificer,
+   ];
+
+   fn id(self) -> i64 {
+      self as i64
+   }
+}
+
+impl DecodeId for God {
+   const KIND: &'static str = "god";
+   const VARIANTS: &'static [God] = &[
+      God::Atheist,
+      God::Zin,
+      God::TheShiningOne,
+      God::Kikubaaqudgha,
+      God::Yredelemnul,
+      God::Xom,
+      God::Vehumet,
+      God::Okawaru,
+      God::Makhleb,
+      God::SifMuna,
+      God::Trog,
+      God::Nemelex,
+      God::Elyvilon,
+      God::Lugonu,
+      God::Beogh,
+      God::Jiyva,
+      God::Fedhas,
+      God::Cheibriados,
+      God::Ashenzari,
+      God::Dithmenos,
+      God::Gozag,
+      God::Qazlal,
+      God::Ru,
+      God::Pakellas,
+      God::Uskayaw,
+      God::Hepliaklqana,
+      God::WuJian,
+   ];
+
+   fn id(self) -> i64 {
+      self as i64
+   }
+}
+
+/// The short form of a species and background used by DCSS, e.g. `MiBe`. Falls back to the full names if either
+/// is unknown.
+pub fn combo_abbreviation(species_id: i64, background_id: i64) -> String {
+   match (Species::decode(species_id), Background::decode(background_id)) {
+      (Decoded::Known(species), Decoded::Known(background)) => {
+         format!(
+            "{}{}",
+            species_abbreviation(species),
+            background_abbreviation(background)
+         )
+      }
+      (species, background) => format!("{} {}", species, background),
+   }
+}
+
+fn species_abbreviation(species: Species) -> &'static str {
+   match species {
+      Species::Human => "Hu",
+      Species::HighElf => "HE",
+      Species::DeepElf => "DE",
+      Species::Halfling => "Ha",
+      Species::HillOrc => "HO",
+      Species::Kobold => "Ko",
+      Species::Mummy => "Mu",
+      Species::Naga => "Na",
+      Species::Ogre => "Og",
+      Species::Troll => "Tr",
+      Species::Draconian => "Dr",
+      Species::Centaur => "Ce",
+      Species::Demigod => "Dg",
+      Species::Spriggan => "Sp",
+      Species::Minotaur => "Mi",
+      Species::Demonspawn => "Ds",
+      Species::Ghoul => "Gh",
+      Species::Tengu => "Te",
+      Species::Merfolk => "Mf",
+      Species::Vampire => "Vp",
+      Species::DeepDwarf => "DD",
+      Species::Felid => "Fe",
+      Species::Octopode => "Op",
+      Species::Gargoyle => "Gr",
+      Species::Formicid => "Fo",
+      Species::VineStalker => "VS",
+      Species::Barachi => "Ba",
+      Species::Gnoll => "Gn",
+   }
+}
+
+fn background_abbreviation(background: Background) -> &'static str {
+   match background {
+      Background::Fighter => "Fi",
+      Background::Wizard => "Wz",
+      Background::Priest => "Pr",
+      Background::Gladiator => "Gl",
+      Background::Necromancer => "Ne",
+      Background::Assassin => "As",
+      Background::Berserker => "Be",
+      Background::Hunter => "Hu",
+      Background::Conjurer => "Cj",
+      Background::Enchanter => "En",
+      Background::FireElementalist => "FE",
+      Background::IceElementalist => "IE",
+      Background::Summoner => "Su",
+      Background::AirElementalist => "AE",
+      Background::EarthElementalist => "EE",
+      Background::Skald => "Sk",
+      Background::VenomMage => "VM",
+      Background::ChaosKnight => "CK",
+      Background::Transmuter => "Tm",
+      Background::Monk => "Mo",
+      Background::Warper => "Wr",
+      Background::Wanderer => "Wn",
+      Background::ArcaneMarksman => "AM",
+      Background::AbyssalKnight => "AK",
+      Background::Artificer => "Ar",
+   }
+}
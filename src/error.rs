@@ -0,0 +1,96 @@
+//! Errors that can occur while handling a request, and how they are presented to the user.
+
+use rocket::http::Status;
+use rocket::response::{self, status, Responder};
+use rocket::Request;
+use rocket_contrib::{Json, Template};
+
+#[derive(Debug)]
+pub enum Error {
+   /// No database connection became available before the pool timed out.
+   Pool(r2d2::Error),
+   /// The database rejected or failed to execute a query.
+   Query(diesel::result::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<r2d2::Error> for Error {
+   fn from(e: r2d2::Error) -> Error {
+      Error::Pool(e)
+   }
+}
+
+impl From<diesel::result::Error> for Error {
+   fn from(e: diesel::result::Error) -> Error {
+      Error::Query(e)
+   }
+}
+
+#[derive(Serialize)]
+pub struct ErrorContext {
+   pub status: u16,
+   pub title: &'static str,
+   pub message: &'static str,
+}
+
+impl ErrorContext {
+   pub fn new(status: Status, message: &'static str) -> ErrorContext {
+      ErrorContext {
+         status: status.code,
+         title: status.reason,
+         message: message,
+      }
+   }
+
+   /// Renders as JSON for API routes and as the error page for everything else.
+   pub fn respond(self, req: &Request) -> response::Result<'static> {
+      let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+      if req.uri().path().starts_with("/api/") {
+         status::Custom(status, Json(self)).respond_to(req)
+      } else {
+         status::Custom(status, Template::render("error", &self)).respond_to(req)
+      }
+   }
+}
+
+impl Responder<'static> for Error {
+   fn respond_to(self, req: &Request) -> response::Result<'static> {
+      let context = match self {
+         Error::Pool(e) => {
+            error!("Timed out waiting for a pooled connection: {}", e);
+            ErrorContext::new(
+               Status::ServiceUnavailable,
+               "The server is too busy to handle your request right now. Please try again in a moment.",
+            )
+         }
+         Error::Query(e) => {
+            error!("Error loading games: {}", e);
+            ErrorContext::new(
+               Status::InternalServerError,
+               "Something went wrong while loading games from the database.",
+            )
+         }
+      };
+      context.respond(req)
+   }
+}
+
+#[error(404)]
+pub fn not_found(req: &Request) -> response::Result<'static> {
+   ErrorContext::new(Status::NotFound, "There's nothing here.").respond(req)
+}
+
+#[error(422)]
+pub fn unprocessable_entity(req: &Request) -> response::Result<'static> {
+   ErrorContext::new(
+      Status::UnprocessableEntity,
+      "One of the query parameters couldn't be understood. Check for typos in species, background or god names.",
+   )
+   .respond(req)
+}
+
+#[error(500)]
+pub fn internal_error(req: &Request) -> response::Result<'static> {
+   ErrorContext::new(Status::InternalServerError, "Something went wrong on our end.").respond(req)
+}
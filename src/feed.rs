@@ -0,0 +1,107 @@
+//! Rendering of recent games as an Atom feed.
+//!
+//! Feed readers need absolute links, so every link is built on the server's public address, taken from the
+//! `BASE_URL` environment variable.
+
+use crate::decode;
+use chrono::{NaiveDateTime, Utc};
+use crawl_model::db_model::Game;
+
+/// How many of the most recent games the feed includes.
+pub const FEED_SIZE: i64 = 50;
+
+/// Query parameters that change how the hiscores are shown but not which games are in the feed.
+const PRESENTATION_PARAMS: [&str; 4] = ["sort_by", "order", "page", "per_page"];
+
+/// The server's public address, e.g. `https://crawl.example.com`, without a trailing slash.
+pub struct BaseUrl(pub String);
+
+impl BaseUrl {
+   pub fn from_env() -> BaseUrl {
+      let base_url = std::env::var("BASE_URL").unwrap_or_else(|_| {
+         warn!("BASE_URL isn't set, links in the Atom feed will point to localhost");
+         "http://localhost:8000".into()
+      });
+      BaseUrl(base_url.trim_end_matches('/').into())
+   }
+}
+
+/// A permanent ID for the feed of games matching `query`, so that feeds with different filters stay apart. The
+/// same filters in any order give the same ID.
+pub fn feed_id(base_url: &BaseUrl, query: Option<&str>) -> String {
+   let mut params: Vec<&str> = query
+      .unwrap_or("")
+      .split('&')
+      .filter(|x| !x.is_empty() && !PRESENTATION_PARAMS.contains(&x.split('=').next().unwrap_or("")))
+      .collect();
+   params.sort();
+   if params.is_empty() {
+      format!("{}/feed.atom", base_url.0)
+   } else {
+      format!("{}/feed.atom?{}", base_url.0, params.join("&"))
+   }
+}
+
+/// `feed_title` describes the filters applied, if any. `games` should be newest first.
+pub fn render(base_url: &BaseUrl, feed_id: &str, feed_title: &str, games: &[Game]) -> String {
+   let updated = games
+      .first()
+      .map(|x| timestamp_to_rfc3339(x.end))
+      .unwrap_or_else(|| Utc::now().to_rfc3339());
+   let mut feed = String::new();
+   feed.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+   feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+   feed.push_str(&format!("  <title>{}</title>\n", escape(feed_title)));
+   feed.push_str(&format!("  <id>{}</id>\n", escape(feed_id)));
+   feed.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", escape(feed_id)));
+   feed.push_str(&format!("  <link href=\"{}/\"/>\n", escape(&base_url.0)));
+   feed.push_str(&format!("  <updated>{}</updated>\n", updated));
+   feed.push_str("  <author><name>crawl_score_serve</name></author>\n");
+   for game in games {
+      feed.push_str("  <entry>\n");
+      feed.push_str(&format!("    <title>{}</title>\n", escape(&entry_title(game))));
+      feed.push_str(&format!("    <id>tag:crawl_score_serve,2018:game/{}</id>\n", game.id));
+      feed.push_str(&format!(
+         "    <link href=\"{}\"/>\n",
+         escape(&format!("{}{}", base_url.0, crate::game_url(game)))
+      ));
+      feed.push_str(&format!("    <updated>{}</updated>\n", timestamp_to_rfc3339(game.end)));
+      feed.push_str(&format!("    <author><name>{}</name></author>\n", escape(&game.name)));
+      feed.push_str("  </entry>\n");
+   }
+   feed.push_str("</feed>\n");
+   feed
+}
+
+/// e.g. `brick the MiBe escaped with 3 runes (score 123456)`.
+fn entry_title(game: &Game) -> String {
+   let outcome = if game.is_victory() {
+      format!("escaped with {} runes", game.runes)
+   } else {
+      format!("{} on {}", game.tmsg, game.place)
+   };
+   format!(
+      "{} the {} {} (score {})",
+      game.name,
+      decode::combo_abbreviation(game.species_id, game.background_id),
+      outcome,
+      game.score
+   )
+}
+
+/// Unlike `dates::format_timestamp` this can't show an out of range timestamp as is, since Atom needs a valid date,
+/// so those fall back to the epoch.
+fn timestamp_to_rfc3339(timestamp: i64) -> String {
+   NaiveDateTime::from_timestamp_opt(timestamp, 0)
+      .unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0))
+      .format("%Y-%m-%dT%H:%M:%SZ")
+      .to_string()
+}
+
+fn escape(text: &str) -> String {
+   text
+      .replace('&', "&amp;")
+      .replace('<', "&lt;")
+      .replace('>', "&gt;")
+      .replace('"', "&quot;")
+}
@@ -2,9 +2,13 @@
 #![feature(plugin, custom_derive)]
 #![plugin(rocket_codegen)]
 
+extern crate chrono;
 extern crate crawl_model;
+#[macro_use]
 extern crate diesel;
 extern crate dotenv;
+#[macro_use]
+extern crate log;
 extern crate r2d2;
 extern crate r2d2_diesel;
 extern crate rocket;
@@ -13,104 +17,573 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
+mod aliases;
+mod dates;
+mod decode;
+mod error;
+mod feed;
+mod milestones;
+mod schema;
+mod stats;
+mod streaks;
+
+use aliases::{AliasTable, Aliases};
+use dates::DateParam;
+use decode::DecodeId;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use dotenv::dotenv;
+use error::Result;
+use rocket::http::uri::URI;
+use rocket::http::ContentType;
+use rocket::response::content::Content;
 use rocket::response::NamedFile;
 use rocket::State;
-use rocket_contrib::Template;
-use std::ops::Deref;
+use rocket_contrib::{Json, Template};
+use stats::{Dimension, DimensionList};
 use std::path::{Path, PathBuf};
 
 type DatabasePool = r2d2::Pool<r2d2_diesel::ConnectionManager<diesel::SqliteConnection>>;
 
-struct Species(crawl_model::data::Species);
+/// A comma separated list of values to match, any of which may be prefixed with `!` to exclude it instead.
+/// `god=Trog,Okawaru` matches either god, `god=!Xom` matches anyone but Xom.
+struct FilterList<T> {
+   include: Vec<T>,
+   exclude: Vec<T>,
+}
+
+impl<T: DecodeId + std::fmt::Debug> FilterList<T> {
+   fn include_ids(&self) -> Vec<i64> {
+      self.include.iter().map(|x| x.id()).collect()
+   }
+
+   fn exclude_ids(&self) -> Vec<i64> {
+      self.exclude.iter().map(|x| x.id()).collect()
+   }
+
+   fn extend(&mut self, other: FilterList<T>) {
+      self.include.extend(other.include);
+      self.exclude.extend(other.exclude);
+   }
 
-impl Deref for Species {
-   type Target = crawl_model::data::Species;
-   fn deref(&self) -> &crawl_model::data::Species {
-      &self.0
+   fn describe(&self) -> String {
+      let include: Vec<String> = self.include.iter().map(|x| format!("{:?}", x)).collect();
+      let exclude: Vec<String> = self.exclude.iter().map(|x| format!("{:?}", x)).collect();
+      match (include.is_empty(), exclude.is_empty()) {
+         (false, true) => include.join(" or "),
+         (true, false) => format!("not {}", exclude.join(" or ")),
+         _ => format!("{}, but not {}", include.join(" or "), exclude.join(" or ")),
+      }
    }
 }
 
-impl<'a> rocket::request::FromFormValue<'a> for Species {
+impl<'a, T: std::str::FromStr> rocket::request::FromFormValue<'a> for FilterList<T> {
    type Error = ();
-   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<Species, ()> {
-      let species = param.percent_decode_lossy().parse::<crawl_model::data::Species>()?;
-      Ok(Species(species))
+   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<FilterList<T>, ()> {
+      let mut list = FilterList {
+         include: vec![],
+         exclude: vec![],
+      };
+      for value in param.percent_decode_lossy().split(',').map(|x| x.trim()) {
+         if value.starts_with('!') {
+            list.exclude.push(value[1..].parse::<T>().map_err(|_| ())?);
+         } else {
+            list.include.push(value.parse::<T>().map_err(|_| ())?);
+         }
+      }
+      Ok(list)
+   }
+}
+
+/// A column of a table that can be sorted by clicking its header.
+trait SortableColumn: Copy + PartialEq + 'static {
+   const ALL: &'static [Self];
+
+   /// The column's name in `sort_by` query parameters.
+   fn key(self) -> &'static str;
+
+   /// The direction the column is sorted in when no order is given: best or most recent first.
+   fn default_order(self) -> SortOrder;
+
+   fn from_key(key: &str) -> Result<Self, ()> {
+      let key = key.to_ascii_lowercase();
+      Self::ALL.iter().find(|x| x.key() == key).cloned().ok_or(())
    }
 }
 
-struct Background(crawl_model::data::Background);
+/// The sort state of a table, and the links for its headers.
+#[derive(Serialize)]
+struct SortLinks {
+   sort_column: &'static str,
+   sort_order: &'static str,
+   /// Column key -> link that sorts by that column, toggling the order if it's already the sort column.
+   sort_urls: std::collections::BTreeMap<&'static str, String>,
+}
 
-impl Deref for Background {
-   type Target = crawl_model::data::Background;
-   fn deref(&self) -> &crawl_model::data::Background {
-      &self.0
+impl SortLinks {
+   fn new<C: SortableColumn>(uri: &URI, column: C, order: SortOrder) -> SortLinks {
+      let sort_urls = C::ALL
+         .iter()
+         .map(|&x| {
+            let new_order = if x == column {
+               order.reverse()
+            } else {
+               x.default_order()
+            };
+            // Any other page of the old order means nothing in the new one
+            let url = with_query_params(
+               uri,
+               &[
+                  ("sort_by", Some(x.key())),
+                  ("order", Some(new_order.key())),
+                  ("page", None),
+               ],
+            );
+            (x.key(), url)
+         })
+         .collect();
+      SortLinks {
+         sort_column: column.key(),
+         sort_order: order.key(),
+         sort_urls: sort_urls,
+      }
    }
 }
 
-impl<'a> rocket::request::FromFormValue<'a> for Background {
-   type Error = ();
-   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<Background, ()> {
-      let bg = param.percent_decode_lossy().parse::<crawl_model::data::Background>()?;
-      Ok(Background(bg))
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+   Name,
+   Score,
+   Species,
+   Background,
+   God,
+   Xl,
+   Runes,
+   Turns,
+   Duration,
+   End,
+}
+
+impl SortableColumn for SortColumn {
+   const ALL: &'static [SortColumn] = &[
+      SortColumn::Name,
+      SortColumn::Score,
+      SortColumn::Species,
+      SortColumn::Background,
+      SortColumn::God,
+      SortColumn::Xl,
+      SortColumn::Runes,
+      SortColumn::Turns,
+      SortColumn::Duration,
+      SortColumn::End,
+   ];
+
+   fn key(self) -> &'static str {
+      match self {
+         SortColumn::Name => "name",
+         SortColumn::Score => "score",
+         SortColumn::Species => "species",
+         SortColumn::Background => "background",
+         SortColumn::God => "god",
+         SortColumn::Xl => "xl",
+         SortColumn::Runes => "runes",
+         SortColumn::Turns => "turns",
+         SortColumn::Duration => "duration",
+         SortColumn::End => "end",
+      }
+   }
+
+   fn default_order(self) -> SortOrder {
+      match self {
+         SortColumn::Name
+         | SortColumn::Species
+         | SortColumn::Background
+         | SortColumn::God
+         | SortColumn::Turns
+         | SortColumn::Duration => SortOrder::Asc,
+         SortColumn::Score | SortColumn::Xl | SortColumn::Runes | SortColumn::End => SortOrder::Desc,
+      }
    }
 }
 
-struct God(crawl_model::data::God);
+#[derive(Clone, Copy, PartialEq)]
+enum SortOrder {
+   Asc,
+   Desc,
+}
+
+impl SortOrder {
+   fn key(self) -> &'static str {
+      match self {
+         SortOrder::Asc => "asc",
+         SortOrder::Desc => "desc",
+      }
+   }
 
-impl Deref for God {
-   type Target = crawl_model::data::God;
-   fn deref(&self) -> &crawl_model::data::God {
-      &self.0
+   fn reverse(self) -> SortOrder {
+      match self {
+         SortOrder::Asc => SortOrder::Desc,
+         SortOrder::Desc => SortOrder::Asc,
+      }
    }
 }
 
-impl<'a> rocket::request::FromFormValue<'a> for God {
+impl<'a> rocket::request::FromFormValue<'a> for SortOrder {
    type Error = ();
-   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<God, ()> {
-      let god = param.percent_decode_lossy().parse::<crawl_model::data::God>()?;
-      Ok(God(god))
+
+   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<SortOrder, ()> {
+      match param.percent_decode_lossy().to_ascii_lowercase().as_ref() {
+         "asc" => Ok(SortOrder::Asc),
+         "desc" => Ok(SortOrder::Desc),
+         _ => Err(()),
+      }
    }
 }
 
-enum SortOption {
-   Longest,
-   Shortest,
-   New,
-   Score,
-   Turns,
+/// The column to sort by, along with the order implied by the `sort_by` value if `order` isn't given.
+struct SortOption {
+   column: SortColumn,
+   order: SortOrder,
+}
+
+impl SortOption {
+   fn new(column: SortColumn) -> SortOption {
+      SortOption {
+         column: column,
+         order: column.default_order(),
+      }
+   }
 }
 
 impl<'a> rocket::request::FromFormValue<'a> for SortOption {
    type Error = ();
 
    fn from_form_value(param: &'a rocket::http::RawStr) -> Result<SortOption, ()> {
-      match param.percent_decode_lossy().to_ascii_lowercase().as_ref() {
-         "longest" => Ok(SortOption::Longest),
-         "shortest" => Ok(SortOption::Shortest),
-         "new" => Ok(SortOption::New),
-         "score" => Ok(SortOption::Score),
-         "turns" => Ok(SortOption::Turns),
-         _ => Err(()),
+      let param = param.percent_decode_lossy().to_ascii_lowercase();
+      match param.as_ref() {
+         "longest" => Ok(SortOption {
+            column: SortColumn::Duration,
+            order: SortOrder::Desc,
+         }),
+         "shortest" => Ok(SortOption::new(SortColumn::Duration)),
+         "new" => Ok(SortOption::new(SortColumn::End)),
+         "dur" => Ok(SortOption::new(SortColumn::Duration)),
+         "turn" => Ok(SortOption::new(SortColumn::Turns)),
+         _ => SortColumn::from_key(&param).map(SortOption::new),
       }
    }
 
    fn default() -> Option<SortOption> {
-      Some(SortOption::Score)
+      Some(SortOption::new(SortColumn::Score))
    }
 }
 
-#[derive(FromForm)]
 struct GameQuery {
-   god: Option<God>,
-   background: Option<Background>,
-   species: Option<Species>,
+   god: Option<FilterList<crawl_model::data::God>>,
+   background: Option<FilterList<crawl_model::data::Background>>,
+   species: Option<FilterList<crawl_model::data::Species>>,
    name: Option<String>,
+   /// A real name from the alias table, matching games on any of their accounts.
+   person: Option<String>,
    runes: Option<i64>,
    victory: Option<bool>,
+   min_score: Option<i64>,
+   max_score: Option<i64>,
+   min_xl: Option<i64>,
+   max_xl: Option<i64>,
+   min_turn: Option<i64>,
+   max_turn: Option<i64>,
+   /// In seconds of real time.
+   min_dur: Option<i64>,
+   max_dur: Option<i64>,
+   min_runes: Option<i64>,
+   max_runes: Option<i64>,
+   /// Games that ended on or after this date.
+   since: Option<DateParam>,
+   /// Games that ended on or before this date.
+   until: Option<DateParam>,
    sort_by: SortOption,
+   order: Option<SortOrder>,
+   page: Option<i64>,
+   per_page: Option<i64>,
+}
+
+/// Filter lists may be given as repeated keys as well as comma separated, so `god=Trog&god=Okawaru` is the same as
+/// `god=Trog,Okawaru`. Any other key may only be given once. A value that doesn't parse fails the whole query,
+/// rather than quietly dropping that filter.
+impl<'f> rocket::request::FromForm<'f> for GameQuery {
+   type Error = ();
+
+   fn from_form(items: &mut rocket::request::FormItems<'f>, strict: bool) -> Result<GameQuery, ()> {
+      let mut query = GameQuery::default();
+      let mut seen = std::collections::HashSet::new();
+      for (key, value) in items {
+         // A blank value, as sent for an empty form field, is the same as leaving the parameter out
+         if value.is_empty() {
+            continue;
+         }
+         macro_rules! parse {
+            () => {
+               rocket::request::FromFormValue::from_form_value(value).map_err(|_| ())?
+            };
+         }
+         macro_rules! list {
+            ($field:ident) => {{
+               let list: FilterList<_> = parse!();
+               match query.$field {
+                  Some(ref mut existing) => existing.extend(list),
+                  None => query.$field = Some(list),
+               }
+            }};
+         }
+         macro_rules! once {
+            ($assignment:expr) => {{
+               if !seen.insert(key.as_str()) {
+                  return Err(());
+               }
+               $assignment;
+            }};
+         }
+         match key.as_str() {
+            "god" => list!(god),
+            "background" => list!(background),
+            "species" => list!(species),
+            "name" => once!(query.name = Some(parse!())),
+            "person" => once!(query.person = Some(parse!())),
+            "runes" => once!(query.runes = Some(parse!())),
+            "victory" => once!(query.victory = Some(parse!())),
+            "min_score" => once!(query.min_score = Some(parse!())),
+            "max_score" => once!(query.max_score = Some(parse!())),
+            "min_xl" => once!(query.min_xl = Some(parse!())),
+            "max_xl" => once!(query.max_xl = Some(parse!())),
+            "min_turn" => once!(query.min_turn = Some(parse!())),
+            "max_turn" => once!(query.max_turn = Some(parse!())),
+            "min_dur" => once!(query.min_dur = Some(parse!())),
+            "max_dur" => once!(query.max_dur = Some(parse!())),
+            "min_runes" => once!(query.min_runes = Some(parse!())),
+            "max_runes" => once!(query.max_runes = Some(parse!())),
+            "since" => once!(query.since = Some(parse!())),
+            "until" => once!(query.until = Some(parse!())),
+            "sort_by" => once!(query.sort_by = parse!()),
+            "order" => once!(query.order = Some(parse!())),
+            "page" => once!(query.page = Some(parse!())),
+            "per_page" => once!(query.per_page = Some(parse!())),
+            _ if strict => return Err(()),
+            _ => (),
+         }
+      }
+      Ok(query)
+   }
+}
+
+const DEFAULT_PER_PAGE: i64 = 100;
+const MAX_PER_PAGE: i64 = 1000;
+
+impl GameQuery {
+   /// The requested page, 1-indexed.
+   fn page(&self) -> i64 {
+      std::cmp::max(self.page.unwrap_or(1), 1)
+   }
+
+   fn per_page(&self) -> i64 {
+      match self.per_page {
+         Some(n) if n >= 1 => std::cmp::min(n, MAX_PER_PAGE),
+         _ => DEFAULT_PER_PAGE,
+      }
+   }
+
+   /// Saturates rather than overflowing on absurd page numbers, which just give an empty page.
+   fn offset(&self) -> i64 {
+      (self.page() - 1).saturating_mul(self.per_page())
+   }
+
+   fn sort_order(&self) -> SortOrder {
+      self.order.unwrap_or(self.sort_by.order)
+   }
+
+   /// Resolves the `person` filter to that person's accounts. An unknown person has no accounts.
+   fn person_accounts<'a>(&self, aliases: &'a AliasTable) -> Option<&'a [String]> {
+      self.person.as_ref().map(|x| aliases.accounts(x).unwrap_or(&[]))
+   }
+
+   /// Every game matching this query's filters, in no particular order.
+   fn filtered<'a>(
+      &'a self,
+      person_accounts: Option<&'a [String]>,
+   ) -> crawl_model::db_schema::games::BoxedQuery<'a, diesel::sqlite::Sqlite> {
+      use crawl_model::db_schema::games::dsl::*;
+      let mut expression = games.into_boxed();
+      if let Some(ref god) = self.god {
+         if !god.include.is_empty() {
+            expression = expression.filter(god_id.eq_any(god.include_ids()));
+         }
+         if !god.exclude.is_empty() {
+            expression = expression.filter(god_id.ne_all(god.exclude_ids()));
+         }
+      }
+      if let Some(ref background) = self.background {
+         if !background.include.is_empty() {
+            expression = expression.filter(background_id.eq_any(background.include_ids()));
+         }
+         if !background.exclude.is_empty() {
+            expression = expression.filter(background_id.ne_all(background.exclude_ids()));
+         }
+      }
+      if let Some(ref species) = self.species {
+         if !species.include.is_empty() {
+            expression = expression.filter(species_id.eq_any(species.include_ids()));
+         }
+         if !species.exclude.is_empty() {
+            expression = expression.filter(species_id.ne_all(species.exclude_ids()));
+         }
+      }
+      if let Some(ref qname) = self.name {
+         expression = expression.filter(name.eq(qname));
+      }
+      if let Some(accounts) = person_accounts {
+         expression = expression.filter(name.eq_any(accounts));
+      }
+      if let Some(nrunes) = self.runes {
+         expression = expression.filter(runes.eq(nrunes));
+      }
+      if let Some(victory) = self.victory {
+         expression = match victory {
+            true => expression.filter(tmsg.eq("escaped with the Orb")),
+            false => expression.filter(tmsg.ne("escaped with the Orb")),
+         };
+      }
+      if let Some(min) = self.min_score {
+         expression = expression.filter(score.ge(min));
+      }
+      if let Some(max) = self.max_score {
+         expression = expression.filter(score.le(max));
+      }
+      if let Some(min) = self.min_xl {
+         expression = expression.filter(xl.ge(min));
+      }
+      if let Some(max) = self.max_xl {
+         expression = expression.filter(xl.le(max));
+      }
+      if let Some(min) = self.min_turn {
+         expression = expression.filter(turn.ge(min));
+      }
+      if let Some(max) = self.max_turn {
+         expression = expression.filter(turn.le(max));
+      }
+      if let Some(min) = self.min_dur {
+         expression = expression.filter(dur.ge(min));
+      }
+      if let Some(max) = self.max_dur {
+         expression = expression.filter(dur.le(max));
+      }
+      if let Some(min) = self.min_runes {
+         expression = expression.filter(runes.ge(min));
+      }
+      if let Some(max) = self.max_runes {
+         expression = expression.filter(runes.le(max));
+      }
+      if let Some(since) = self.since {
+         expression = expression.filter(end.ge(since.start_timestamp()));
+      }
+      if let Some(until) = self.until {
+         expression = expression.filter(end.le(until.end_timestamp()));
+      }
+      expression
+   }
+
+   /// Every game matching this query's filters, sorted as requested.
+   fn sorted<'a>(
+      &'a self,
+      person_accounts: Option<&'a [String]>,
+   ) -> crawl_model::db_schema::games::BoxedQuery<'a, diesel::sqlite::Sqlite> {
+      use crawl_model::db_schema::games::dsl::*;
+      let mut expression = self.filtered(person_accounts);
+      macro_rules! order_by {
+         ($column:expr, $order:expr) => {
+            expression = match $order {
+               SortOrder::Asc => expression.then_order_by($column.asc()),
+               SortOrder::Desc => expression.then_order_by($column.desc()),
+            }
+         };
+      }
+      let order = self.sort_order();
+      match self.sort_by.column {
+         SortColumn::Name => order_by!(name, order),
+         SortColumn::Score => order_by!(score, order),
+         SortColumn::Species => {
+            let species_name = decode::name_sql::<crawl_model::data::Species>("games.species_id");
+            order_by!(diesel::dsl::sql::<diesel::sql_types::Text>(&species_name), order)
+         }
+         SortColumn::Background => {
+            let background_name = decode::name_sql::<crawl_model::data::Background>("games.background_id");
+            order_by!(diesel::dsl::sql::<diesel::sql_types::Text>(&background_name), order)
+         }
+         SortColumn::God => {
+            let god_name = decode::name_sql::<crawl_model::data::God>("games.god_id");
+            order_by!(diesel::dsl::sql::<diesel::sql_types::Text>(&god_name), order)
+         }
+         SortColumn::Xl => order_by!(xl, order),
+         SortColumn::Runes => order_by!(runes, order),
+         SortColumn::Turns => order_by!(turn, order),
+         SortColumn::Duration => order_by!(dur, order),
+         SortColumn::End => order_by!(end, order),
+      }
+      // Tie-breakers, so that ties don't shuffle between pages. The ID comes last as it's the only unique key.
+      expression = expression
+         .then_order_by(score.desc())
+         .then_order_by(end.desc())
+         .then_order_by(name.asc())
+         .then_order_by(id.asc());
+      expression
+   }
+
+   /// Human readable descriptions of every filter in effect, for echoing back on the page.
+   fn active_filters(&self) -> Vec<String> {
+      let mut filters = vec![];
+      if let Some(ref god) = self.god {
+         filters.push(format!("god: {}", god.describe()));
+      }
+      if let Some(ref background) = self.background {
+         filters.push(format!("background: {}", background.describe()));
+      }
+      if let Some(ref species) = self.species {
+         filters.push(format!("species: {}", species.describe()));
+      }
+      if let Some(ref name) = self.name {
+         filters.push(format!("name: {}", name));
+      }
+      if let Some(ref person) = self.person {
+         filters.push(format!("person: {}", person));
+      }
+      if let Some(runes) = self.runes {
+         filters.push(format!("runes: {}", runes));
+      }
+      if let Some(victory) = self.victory {
+         filters.push(if victory { "wins only" } else { "deaths only" }.into());
+      }
+      let ranges = [
+         ("score", self.min_score, self.max_score),
+         ("XL", self.min_xl, self.max_xl),
+         ("turns", self.min_turn, self.max_turn),
+         ("duration (seconds)", self.min_dur, self.max_dur),
+         ("runes", self.min_runes, self.max_runes),
+      ];
+      for &(label, min, max) in ranges.iter() {
+         match (min, max) {
+            (Some(min), Some(max)) => filters.push(format!("{} between {} and {}", label, min, max)),
+            (Some(min), None) => filters.push(format!("{} at least {}", label, min)),
+            (None, Some(max)) => filters.push(format!("{} at most {}", label, max)),
+            (None, None) => (),
+         }
+      }
+      if let Some(since) = self.since {
+         filters.push(format!("ended since {}", since));
+      }
+      if let Some(until) = self.until {
+         filters.push(format!("ended until {}", until));
+      }
+      filters
+   }
 }
 
 impl Default for GameQuery {
@@ -120,22 +593,87 @@ impl Default for GameQuery {
          background: None,
          species: None,
          name: None,
+         person: None,
          runes: None,
          victory: None,
-         sort_by: SortOption::Score,
+         min_score: None,
+         max_score: None,
+         min_xl: None,
+         max_xl: None,
+         min_turn: None,
+         max_turn: None,
+         min_dur: None,
+         max_dur: None,
+         min_runes: None,
+         max_runes: None,
+         since: None,
+         until: None,
+         sort_by: SortOption::new(SortColumn::Score),
+         order: None,
+         page: None,
+         per_page: None,
       }
    }
 }
 
+/// Bumped whenever the shape of a JSON API response changes incompatibly.
+const API_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct ApiResponse<T> {
+   api_version: u32,
+   #[serde(flatten)]
+   data: T,
+}
+
+impl<T> ApiResponse<T> {
+   fn new(data: T) -> Json<ApiResponse<T>> {
+      Json(ApiResponse {
+         api_version: API_VERSION,
+         data: data,
+      })
+   }
+}
+
 #[derive(Serialize)]
 struct IndexContext {
    games: Vec<FormattedGame>,
    total_count: i64,
    matched_count: i64,
+   filters: Vec<String>,
+   page: i64,
+   per_page: i64,
+   /// Number of games on earlier pages, so that ranks continue across pages.
+   rank_offset: i64,
+   prev_url: Option<String>,
+   next_url: Option<String>,
+   #[serde(flatten)]
+   sort: SortLinks,
+}
+
+/// Rebuilds the query string of `uri` with each key in `changes` set to the given value, or removed if the value
+/// is `None`. Any existing occurrences of those keys are replaced.
+fn with_query_params(uri: &URI, changes: &[(&str, Option<&str>)]) -> String {
+   let mut params: Vec<String> = uri
+      .query()
+      .unwrap_or("")
+      .split('&')
+      .filter(|x| !x.is_empty() && !changes.iter().any(|c| x.split('=').next() == Some(c.0)))
+      .map(String::from)
+      .collect();
+   for &(key, value) in changes {
+      if let Some(value) = value {
+         params.push(format!("{}={}", key, value));
+      }
+   }
+   format!("{}?{}", uri.path(), params.join("&"))
 }
 
 #[derive(Serialize)]
 struct FormattedGame {
+   pub id: i64,
+   /// Permalink to the game's own page.
+   pub url: String,
    pub real_name: String,
    pub name: String,
    pub score: i64,
@@ -147,18 +685,383 @@ struct FormattedGame {
    pub victory: bool,
    pub duration: String,
    pub turns: i64,
+   pub end: String,
+   /// How the game ended, e.g. `slain by an orc`.
+   pub tmsg: String,
+   pub place: String,
+}
+
+#[derive(Serialize)]
+struct LiveContext {
+   pub games: Vec<LiveGame>,
+}
+
+/// A game in progress, as of its latest milestone.
+#[derive(Serialize)]
+struct LiveGame {
+   pub name: String,
+   pub real_name: String,
+   pub species: String,
+   pub background: String,
+   pub god: String,
+   pub xl: i64,
+   pub place: String,
+   pub turn: i64,
+   pub runes: i64,
+   pub started: String,
+   /// e.g. `found a slimy rune of Zot.`
+   pub latest: String,
+   pub latest_time: String,
+   /// How long ago the latest milestone was, e.g. `3 hours ago`.
+   pub age: String,
+}
+
+#[derive(Serialize)]
+struct GameContext {
+   pub game: FormattedGame,
+   /// Realtime duration in seconds, alongside the readable `game.duration`.
+   pub dur: i64,
+   pub player_url: String,
+   /// Set when the account belongs to someone in the alias table.
+   pub person_url: Option<String>,
+   pub hiscores_url: String,
+   /// Hiscores for the same species and background. Not set if either is unknown to us.
+   pub combo_url: Option<String>,
+   /// Empty if no milestones have been imported for this game.
+   pub timeline: Vec<FormattedMilestone>,
+   pub runes_found: Vec<milestones::RuneFound>,
+   pub god_joined: Option<GodJoined>,
+}
+
+#[derive(Serialize)]
+struct FormattedMilestone {
+   pub turn: i64,
+   pub place: String,
+   pub time: String,
+   pub message: String,
+}
+
+#[derive(Serialize)]
+struct GodJoined {
+   pub god: String,
+   pub turn: i64,
+   pub place: String,
 }
 
 #[derive(Serialize)]
-struct FreqContext<'a> {
-   name: &'a str,
+struct FreqContext {
+   columns: Vec<&'static str>,
    items: Vec<FormattedFreqItem>,
+   filters: Vec<String>,
+   /// Whether the outcome columns (wins, win rate, ...) should be shown.
+   show_outcomes: bool,
 }
 
 #[derive(Serialize)]
 struct FormattedFreqItem {
    pub frequency: i64,
+   pub values: Vec<String>,
+   pub wins: i64,
+   pub winrate: String,
+   pub avg_xl: String,
+   pub avg_runes: String,
+   pub best_score: i64,
+}
+
+impl From<stats::Group> for FormattedFreqItem {
+   fn from(group: stats::Group) -> FormattedFreqItem {
+      FormattedFreqItem {
+         frequency: group.games,
+         values: group.values,
+         wins: group.wins,
+         winrate: format!("{:.2}", (group.wins as f64 / group.games as f64) * 100.0),
+         avg_xl: format!("{:.1}", group.avg_xl),
+         avg_runes: format!("{:.1}", group.avg_runes),
+         best_score: group.best_score,
+      }
+   }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PlayerColumn {
+   Name,
+   Games,
+   Wins,
+   Winrate,
+   Runes,
+   BestScore,
+   FastestWin,
+   LastPlayed,
+}
+
+impl SortableColumn for PlayerColumn {
+   const ALL: &'static [PlayerColumn] = &[
+      PlayerColumn::Name,
+      PlayerColumn::Games,
+      PlayerColumn::Wins,
+      PlayerColumn::Winrate,
+      PlayerColumn::Runes,
+      PlayerColumn::BestScore,
+      PlayerColumn::FastestWin,
+      PlayerColumn::LastPlayed,
+   ];
+
+   fn key(self) -> &'static str {
+      match self {
+         PlayerColumn::Name => "name",
+         PlayerColumn::Games => "games",
+         PlayerColumn::Wins => "wins",
+         PlayerColumn::Winrate => "winrate",
+         PlayerColumn::Runes => "runes",
+         PlayerColumn::BestScore => "best_score",
+         PlayerColumn::FastestWin => "fastest_win",
+         PlayerColumn::LastPlayed => "last_played",
+      }
+   }
+
+   fn default_order(self) -> SortOrder {
+      match self {
+         PlayerColumn::Name | PlayerColumn::FastestWin => SortOrder::Asc,
+         _ => SortOrder::Desc,
+      }
+   }
+}
+
+impl<'a> rocket::request::FromFormValue<'a> for PlayerColumn {
+   type Error = ();
+
+   fn from_form_value(param: &'a rocket::http::RawStr) -> Result<PlayerColumn, ()> {
+      PlayerColumn::from_key(&param.percent_decode_lossy())
+   }
+}
+
+#[derive(FromForm)]
+struct PlayerQuery {
+   sort_by: Option<PlayerColumn>,
+   order: Option<SortOrder>,
+}
+
+#[derive(Serialize)]
+struct PlayersContext {
+   players: Vec<FormattedPlayer>,
+   #[serde(flatten)]
+   sort: SortLinks,
+}
+
+#[derive(Serialize)]
+struct FormattedPlayer {
+   pub name: String,
+   pub real_name: String,
+   pub games: i64,
+   pub wins: i64,
+   pub winrate: String,
+   pub total_runes: i64,
+   pub best_score: i64,
+   pub fastest_win: Option<i64>,
+   pub last_played: String,
+}
+
+#[derive(Serialize)]
+struct ComboContext {
+   backgrounds: Vec<String>,
+   rows: Vec<ComboRow>,
+   filters: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ComboRow {
+   species: String,
+   /// One cell per background, in the same order as `ComboContext::backgrounds`.
+   cells: Vec<ComboCell>,
+}
+
+#[derive(Serialize)]
+struct ComboCell {
+   games: i64,
+   wins: i64,
+   /// CSS class used to colour the cell.
+   class: &'static str,
+}
+
+#[derive(Serialize)]
+struct CompareContext {
+   players: Vec<UserContext>,
+   shared: Vec<SharedSection>,
+}
+
+#[derive(Serialize)]
+struct SharedSection {
+   title: &'static str,
+   rows: Vec<SharedRow>,
+}
+
+/// A species, god or combo that every compared player has played.
+#[derive(Serialize)]
+struct SharedRow {
+   value: String,
+   /// One cell per player, in the same order as `CompareContext::players`.
+   cells: Vec<SharedCell>,
+   /// The player who did best with it: highest win rate, then highest score.
+   leader: String,
+}
+
+#[derive(Serialize)]
+struct SharedCell {
+   games: i64,
+   wins: i64,
+   winrate: String,
+   best_score: i64,
+}
+
+/// Raw per-player (or server-wide) statistics, before any formatting for display.
+#[derive(Serialize)]
+struct UserStats {
+   pub name: Option<String>,
+   pub accounts: Vec<String>,
+   pub games: i64,
+   pub wins: i64,
+   /// Fraction of games won, or `None` if no games have been played.
+   pub winrate: Option<f64>,
+   pub num_runes: i64,
+   pub fav_species: Option<String>,
+   pub fav_background: Option<String>,
+   pub fav_combo: Option<Combo>,
+   pub fav_god: Option<String>,
+   pub nemesis: Option<String>,
+   pub death_spot: Option<String>,
+   pub records: Records,
+   /// Length of the streak still in progress on any of the accounts, or 0 if the last game was a loss. Streaks
+   /// aren't tracked server-wide, so this and `best_streak` are `None` for everyone.
+   pub current_streak: Option<i64>,
+   pub best_streak: Option<StreakSummary>,
+}
+
+/// Personal bests. Each is `None` if no game qualifies.
+#[derive(Serialize)]
+struct Records {
+   pub highest_score: Option<RecordGame>,
+   pub fastest_win_turns: Option<RecordGame>,
+   pub fastest_win_time: Option<RecordGame>,
+   pub highest_xl_death: Option<RecordGame>,
+   pub most_runes: Option<RecordGame>,
+   pub longest_survival: Option<RecordGame>,
+}
+
+#[derive(Serialize)]
+struct RecordGame {
+   pub name: String,
+   pub species: String,
+   pub background: String,
+   pub god: String,
+   pub score: i64,
+   pub xl: i64,
+   pub runes: i64,
+   pub turns: i64,
+   /// In seconds of real time.
+   pub dur: i64,
+   pub url: String,
+}
+
+impl From<crawl_model::db_model::Game> for RecordGame {
+   fn from(game: crawl_model::db_model::Game) -> RecordGame {
+      RecordGame {
+         url: game_url(&game),
+         species: crawl_model::data::Species::decode(game.species_id).to_string(),
+         background: crawl_model::data::Background::decode(game.background_id).to_string(),
+         god: crawl_model::data::God::decode(game.god_id).to_string(),
+         name: game.name,
+         score: game.score,
+         xl: game.xl,
+         runes: game.runes,
+         turns: game.turn,
+         dur: game.dur,
+      }
+   }
+}
+
+#[derive(Serialize)]
+struct StreakSummary {
+   pub name: String,
+   pub length: i64,
+   pub ongoing: bool,
+   /// End time of the first win.
+   pub start: String,
+   /// End time of the last win.
+   pub end: String,
+   pub games: Vec<RecordGame>,
+}
+
+impl StreakSummary {
+   /// `games` must hold every game in the streak, by ID.
+   fn new(
+      streak: streaks::Streak,
+      games: &mut std::collections::HashMap<i64, crawl_model::db_model::Game>,
+   ) -> StreakSummary {
+      let start = streak
+         .games
+         .first()
+         .map(|x| dates::format_timestamp(x.end))
+         .unwrap_or_default();
+      let end = streak
+         .games
+         .last()
+         .map(|x| dates::format_timestamp(x.end))
+         .unwrap_or_default();
+      StreakSummary {
+         name: streak.name,
+         length: streak.games.len() as i64,
+         ongoing: streak.ongoing,
+         start: start,
+         end: end,
+         games: streak
+            .games
+            .iter()
+            .filter_map(|x| games.remove(&x.id))
+            .map(RecordGame::from)
+            .collect(),
+      }
+   }
+}
+
+/// How many games `summarize_streaks` loads per query.
+const STREAK_GAMES_CHUNK: usize = 500;
+
+/// Loads the full rows of just the games in `streaks`, and summarizes each streak.
+fn summarize_streaks(connection: &SqliteConnection, all_streaks: Vec<streaks::Streak>) -> Result<Vec<StreakSummary>> {
+   use crawl_model::db_schema::games::dsl::*;
+   let ids: Vec<i64> = all_streaks.iter().flat_map(|x| x.games.iter().map(|x| x.id)).collect();
+   let mut streak_games = std::collections::HashMap::new();
+   // In chunks, to stay under SQLite's limit of 999 bound variables per query
+   for chunk in ids.chunks(STREAK_GAMES_CHUNK) {
+      for game in games
+         .filter(id.eq_any(chunk.to_vec()))
+         .load::<crawl_model::db_model::Game>(connection)?
+      {
+         streak_games.insert(game.id, game);
+      }
+   }
+   Ok(all_streaks
+      .into_iter()
+      .map(|x| StreakSummary::new(x, &mut streak_games))
+      .collect())
+}
+
+#[derive(Serialize)]
+struct StreaksContext {
+   pub streaks: Vec<StreakSummary>,
+}
+
+#[derive(Serialize)]
+struct FormattedRecord {
+   pub title: &'static str,
    pub value: String,
+   pub game: RecordGame,
+}
+
+#[derive(Serialize)]
+struct Combo {
+   pub species: String,
+   pub background: String,
 }
 
 #[derive(Serialize)]
@@ -170,10 +1073,18 @@ struct UserContext {
    pub games: i64,
    pub winrate: String,
    pub name: String,
+   /// Set when this page covers several accounts.
+   pub accounts: Vec<String>,
+   pub games_url: String,
+   pub wins_url: String,
    pub nemesis: String,
    pub death_spot: String,
    pub num_runes: i64,
    pub fav_combo: String,
+   pub records: Vec<FormattedRecord>,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub current_streak: Option<i64>,
+   pub best_streak: Option<StreakSummary>,
 }
 
 fn seconds_to_humantime(mut seconds: i64) -> String {
@@ -196,454 +1107,926 @@ fn seconds_to_humantime(mut seconds: i64) -> String {
    }
 }
 
-impl From<crawl_model::db_model::Game> for FormattedGame {
-   fn from(game: crawl_model::db_model::Game) -> FormattedGame {
-      let species = unsafe { std::mem::transmute::<i64, crawl_model::data::Species>(game.species_id) };
-      let background = unsafe { std::mem::transmute::<i64, crawl_model::data::Background>(game.background_id) };
-      let god = unsafe { std::mem::transmute::<i64, crawl_model::data::God>(game.god_id) };
-      let real_name = match game.name.as_str() {
-         "brick" => "Richard",
-         "Peen" | "paul" => "Paul",
-         "max" | "PunishedMax" | "OgreStreak" => "Max",
-         "daddy" | "fuckboy3000" | "peepeedarts" => "James",
-         "sweetBro" => "Luca",
-         "hellaJeff" | "bigBootyJudy" => "Ben H",
-         "Richard" | "BoonShekel" | "THEBLIMP" | "xXBloodSuckerXx" => "Ben S",
-         "bobjr93" => "Brennan",
-         "jish" => "Josh S",
-         "GrapeApe" => "Mason C.",
-         "Doomlord5" => "Dan",
-         "MikeyBoy" => "Mike",
-         "BigSweetPP" => "Seth",
-         "Idyll" => "Emma",
-         _ => "?",
-      };
+fn game_url(game: &crawl_model::db_model::Game) -> String {
+   format!("/game/{}", game.id)
+}
+
+impl FormattedGame {
+   fn new(game: crawl_model::db_model::Game, aliases: &AliasTable) -> FormattedGame {
+      let species = crawl_model::data::Species::decode(game.species_id);
+      let background = crawl_model::data::Background::decode(game.background_id);
+      let god = crawl_model::data::God::decode(game.god_id);
       let victory = game.is_victory();
       FormattedGame {
+         id: game.id,
+         url: game_url(&game),
+         real_name: aliases.real_name(&game.name).into(),
          name: game.name,
-         real_name: String::from(real_name),
          score: game.score,
-         species: format!("{:?}", species),
-         background: format!("{:?}", background),
-         god: format!("{:?}", god),
+         species: species.to_string(),
+         background: background.to_string(),
+         god: god.to_string(),
          runes: game.runes,
          xl: game.xl,
          victory: victory,
          duration: seconds_to_humantime(game.dur),
          turns: game.turn,
+         end: dates::format_timestamp(game.end),
+         tmsg: game.tmsg,
+         place: game.place,
       }
    }
 }
 
 #[get("/")]
-fn hiscores(state: State<DatabasePool>) -> Template {
-   hi_query(state, GameQuery::default())
+fn hiscores(state: State<DatabasePool>, aliases: State<Aliases>, uri: &URI) -> Result<Template> {
+   hi_query(state, aliases, uri, GameQuery::default())
 }
 
-#[get("/?<game_query>")]
-fn hi_query(state: State<DatabasePool>, game_query: GameQuery) -> Template {
-   fn get_query<'a>(
-      game_query: &'a GameQuery,
-   ) -> crawl_model::db_schema::games::BoxedQuery<'a, diesel::sqlite::Sqlite> {
-      use crawl_model::db_schema::games::dsl::*;
-      let mut expression = games.into_boxed();
-      match game_query.sort_by {
-         SortOption::Shortest => {
-            expression = expression.order(dur.asc());
-         }
-         SortOption::Longest => {
-            expression = expression.order(dur.desc());
-         }
-         SortOption::New => {
-            expression = expression.order(end.desc());
-         }
-         SortOption::Score => {
-            expression = expression.order(score.desc());
-         }
-         SortOption::Turns => {
-            expression = expression.order(turn.asc());
-         }
-      }
-      if let Some(ref god) = game_query.god {
-         expression = expression.filter(god_id.eq(**god as i64));
-      }
-      if let Some(ref background) = game_query.background {
-         expression = expression.filter(background_id.eq(**background as i64));
-      }
-      if let Some(ref species) = game_query.species {
-         expression = expression.filter(species_id.eq(**species as i64));
-      }
-      if let Some(ref qname) = game_query.name {
-         expression = expression.filter(name.eq(qname));
-      }
-      if let Some(nrunes) = game_query.runes {
-         expression = expression.filter(runes.eq(nrunes));
-      }
-      if let Some(victory) = game_query.victory {
-         expression = match victory {
-            true => expression.filter(tmsg.eq("escaped with the Orb")),
-            false => expression.filter(tmsg.ne("escaped with the Orb")),
-         };
-      }
-      expression
-   }
-   let connection = state.get().expect("Timeout waiting for pooled connection");
+fn get_index_context(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   uri: &URI,
+   game_query: GameQuery,
+) -> Result<IndexContext> {
+   let aliases = aliases.current();
+   let person_accounts = game_query.person_accounts(&aliases);
+   let connection = state.get()?;
    let games = {
-      let expression = get_query(&game_query);
+      let expression = game_query.sorted(person_accounts);
       expression
-         .limit(100)
-         .load::<crawl_model::db_model::Game>(&*connection)
-         .expect("Error loading games")
+         .offset(game_query.offset())
+         .limit(game_query.per_page())
+         .load::<crawl_model::db_model::Game>(&*connection)?
    };
    let matched_count: i64 = {
-      let expression = get_query(&game_query);
-      expression
-         .count()
-         .get_result(&*connection)
-         .expect("Error loading games")
+      let expression = game_query.filtered(person_accounts);
+      expression.count().get_result(&*connection)?
    };
-   let formatted_games = games.into_iter().map(|x| x.into()).collect();
+   let formatted_games = games.into_iter().map(|x| FormattedGame::new(x, &aliases)).collect();
    let total_count: i64 = {
       use crawl_model::db_schema::games::dsl::*;
-      games.count().get_result(&*connection).expect("Error loading games")
+      games.count().get_result(&*connection)?
+   };
+   let page = game_query.page();
+   let prev_url = if page > 1 {
+      Some(with_query_params(uri, &[("page", Some(&(page - 1).to_string()))]))
+   } else {
+      None
+   };
+   let next_url = if game_query.offset().saturating_add(game_query.per_page()) < matched_count {
+      Some(with_query_params(uri, &[("page", Some(&(page + 1).to_string()))]))
+   } else {
+      None
    };
-   let context = IndexContext {
+   Ok(IndexContext {
       games: formatted_games,
       total_count: total_count,
       matched_count: matched_count,
+      filters: game_query.active_filters(),
+      page: page,
+      per_page: game_query.per_page(),
+      rank_offset: game_query.offset(),
+      prev_url: prev_url,
+      next_url: next_url,
+      sort: SortLinks::new(uri, game_query.sort_by.column, game_query.sort_order()),
+   })
+}
+
+#[get("/?<game_query>")]
+fn hi_query(state: State<DatabasePool>, aliases: State<Aliases>, uri: &URI, game_query: GameQuery) -> Result<Template> {
+   let context = get_index_context(state, aliases, uri, game_query)?;
+   Ok(Template::render("index", &context))
+}
+
+#[get("/api/games")]
+fn api_games(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   uri: &URI,
+) -> Result<Json<ApiResponse<IndexContext>>> {
+   api_games_query(state, aliases, uri, GameQuery::default())
+}
+
+#[get("/api/games?<game_query>")]
+fn api_games_query(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   uri: &URI,
+   game_query: GameQuery,
+) -> Result<Json<ApiResponse<IndexContext>>> {
+   Ok(ApiResponse::new(get_index_context(state, aliases, uri, game_query)?))
+}
+
+#[get("/game/<id>")]
+fn game(state: State<DatabasePool>, aliases: State<Aliases>, id: i64) -> Result<Option<Template>> {
+   let connection = state.get()?;
+   let game: Option<crawl_model::db_model::Game> = {
+      use crawl_model::db_schema::games::dsl::{games, id as game_id};
+      games.filter(game_id.eq(id)).first(&*connection).optional()?
+   };
+   let game = match game {
+      Some(game) => game,
+      None => return Ok(None),
+   };
+   let aliases = aliases.current();
+   let combo_url = match (
+      crawl_model::data::Species::decode(game.species_id).known(),
+      crawl_model::data::Background::decode(game.background_id).known(),
+   ) {
+      (Some(species), Some(background)) => Some(format!("/?species={:?}&background={:?}", species, background)),
+      _ => None,
    };
-   Template::render("index", &context)
+   let real_name = aliases.real_name(&game.name);
+   let person_url = aliases
+      .accounts(real_name)
+      .map(|_| format!("/p/{}", URI::percent_encode(real_name)));
+   let game_milestones = milestones::for_game(&*connection, &game.name, game.end)?;
+   let god_joined = milestones::god_joined(&game_milestones).map(|x| GodJoined {
+      god: crawl_model::data::God::decode(x.god_id).to_string(),
+      turn: x.turn,
+      place: x.place.clone(),
+   });
+   let context = GameContext {
+      runes_found: milestones::runes_found(&game_milestones),
+      god_joined: god_joined,
+      timeline: game_milestones
+         .into_iter()
+         .map(|x| FormattedMilestone {
+            turn: x.turn,
+            place: x.place,
+            time: dates::format_timestamp(x.time),
+            message: x.message,
+         })
+         .collect(),
+      player_url: format!("/u/{}", URI::percent_encode(&game.name)),
+      person_url: person_url,
+      hiscores_url: format!("/?name={}", URI::percent_encode(&game.name)),
+      combo_url: combo_url,
+      dur: game.dur,
+      game: FormattedGame::new(game, &aliases),
+   };
+   Ok(Some(Template::render("game", &context)))
 }
 
-fn get_user_context(state: State<DatabasePool>, name_param: Option<String>) -> UserContext {
-   fn get_query<'a>(
-      name_param: Option<&'a String>,
-   ) -> crawl_model::db_schema::games::BoxedQuery<'a, diesel::sqlite::Sqlite> {
+/// How recent a game's latest milestone must be for it to count as still being played.
+const LIVE_WINDOW_DAYS: i64 = 3;
+
+/// Games that have started but not yet finished, going by the milestones imported so far.
+#[get("/live")]
+fn live(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   let connection = state.get()?;
+   let aliases = aliases.current();
+   let since = (chrono::Utc::now() - chrono::Duration::days(LIVE_WINDOW_DAYS)).timestamp();
+   let games = milestones::in_progress(&*connection, since)?
+      .into_iter()
+      .map(|x| LiveGame {
+         real_name: aliases.real_name(&x.name).into(),
+         name: x.name,
+         species: crawl_model::data::Species::decode(x.species_id).to_string(),
+         background: crawl_model::data::Background::decode(x.background_id).to_string(),
+         god: crawl_model::data::God::decode(x.god_id).to_string(),
+         xl: x.xl,
+         place: x.place,
+         turn: x.turn,
+         runes: x.runes,
+         started: dates::format_timestamp(x.start),
+         latest: x.message,
+         latest_time: dates::format_timestamp(x.time),
+         age: dates::format_age(x.time),
+      })
+      .collect();
+   Ok(Template::render("live", &LiveContext { games: games }))
+}
+
+#[get("/feed.atom")]
+fn atom_feed(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   base_url: State<feed::BaseUrl>,
+   uri: &URI,
+) -> Result<Content<String>> {
+   atom_feed_query(state, aliases, base_url, uri, GameQuery::default())
+}
+
+/// The most recent games matching the query, newest first. Sorting and paging parameters are ignored.
+#[get("/feed.atom?<game_query>")]
+fn atom_feed_query(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   base_url: State<feed::BaseUrl>,
+   uri: &URI,
+   game_query: GameQuery,
+) -> Result<Content<String>> {
+   use crawl_model::db_schema::games::dsl::end;
+   let aliases = aliases.current();
+   let person_accounts = game_query.person_accounts(&aliases);
+   let connection = state.get()?;
+   let games: Vec<crawl_model::db_model::Game> = game_query
+      .filtered(person_accounts)
+      .order(end.desc())
+      .limit(feed::FEED_SIZE)
+      .load(&*connection)?;
+   let filters = game_query.active_filters();
+   let title = if filters.is_empty() {
+      "Recent games".to_string()
+   } else {
+      format!("Recent games with {}", filters.join(", "))
+   };
+   Ok(Content(
+      ContentType::new("application", "atom+xml"),
+      feed::render(&base_url, &feed::feed_id(&base_url, uri.query()), &title, &games),
+   ))
+}
+
+/// Whose games a set of statistics is computed over.
+enum Player {
+   Everyone,
+   Account(String),
+   /// A person from the alias table, and every account that belongs to them.
+   Person(String, Vec<String>),
+}
+
+impl Player {
+   fn get_query<'a>(&'a self) -> crawl_model::db_schema::games::BoxedQuery<'a, diesel::sqlite::Sqlite> {
       use crawl_model::db_schema::games::dsl::*;
-      if let Some(val) = name_param {
-         games.filter(name.eq(val)).into_boxed()
-      } else {
-         games.into_boxed()
+      match *self {
+         Player::Everyone => games.into_boxed(),
+         Player::Account(ref val) => games.filter(name.eq(val)).into_boxed(),
+         Player::Person(_, ref accounts) => games.filter(name.eq_any(accounts)).into_boxed(),
       }
    }
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let num_games: i64 = {
-      get_query(name_param.as_ref())
-         .count()
-         .get_result(&*connection)
-         .expect("Error loading games")
-   };
+
+   /// The hiscores query parameter selecting this player's games, if any.
+   fn query_param(&self) -> Option<String> {
+      match *self {
+         Player::Everyone => None,
+         Player::Account(ref val) => Some(format!("name={}", URI::percent_encode(val))),
+         Player::Person(ref val, _) => Some(format!("person={}", URI::percent_encode(val))),
+      }
+   }
+}
+
+/// Every `tmsg` for a game that ended some way other than the character dying.
+const NOT_DEATHS: [&str; 4] = [
+   "escaped with the Orb",
+   "got out of the dungeon alive",
+   "safely got out of the dungeon",
+   "quit the game",
+];
+
+fn get_user_stats(pool: &DatabasePool, player: &Player) -> Result<UserStats> {
+   let connection = pool.get()?;
+   let num_games: i64 = { player.get_query().count().get_result(&*connection)? };
    let num_wins: i64 = {
       use crawl_model::db_schema::games::dsl::*;
-      get_query(name_param.as_ref())
+      player
+         .get_query()
          .filter(tmsg.eq("escaped with the Orb"))
          .count()
-         .get_result(&*connection)
-         .expect("Error loading games")
+         .get_result(&*connection)?
    };
    let num_runes: i64 = {
       use diesel::dsl::sql;
       use diesel::sql_types::Double;
-      get_query(name_param.as_ref())
-         .select(sql::<Double>("SUM(games.runes)"))
+      player
+         .get_query()
+         .select(sql::<Double>("COALESCE(SUM(games.runes), 0)"))
          .first(&*connection)
-         .optional()
-         .expect("Error loading games")
+         .optional()?
          .unwrap_or(0.0) as i64
    };
    let fav_bg = {
       let fav_bg_id: Option<i64> = {
          use crawl_model::db_schema::games::dsl::*;
          use diesel::dsl::count;
-         get_query(name_param.as_ref())
+         player
+            .get_query()
             .order(count(background_id).desc())
             .select(background_id)
             .group_by(background_id)
             .first(&*connection)
-            .optional()
-            .expect("Error loading games")
+            .optional()?
       };
-      if let Some(bg_id) = fav_bg_id {
-         let background = unsafe { std::mem::transmute::<i64, crawl_model::data::Background>(bg_id) };
-         format!("{:?}", background)
-      } else {
-         "N/A".into()
-      }
+      fav_bg_id.map(|bg_id| crawl_model::data::Background::decode(bg_id).to_string())
    };
    let fav_species = {
       let fav_species_id: Option<i64> = {
          use crawl_model::db_schema::games::dsl::*;
          use diesel::dsl::count;
-         get_query(name_param.as_ref())
+         player
+            .get_query()
             .order(count(species_id).desc())
             .select(species_id)
             .group_by(species_id)
             .first(&*connection)
-            .optional()
-            .expect("Error loading games")
+            .optional()?
       };
-      if let Some(species_id) = fav_species_id {
-         let species = unsafe { std::mem::transmute::<i64, crawl_model::data::Species>(species_id) };
-         format!("{:?}", species)
-      } else {
-         "N/A".into()
-      }
+      fav_species_id.map(|species_id| crawl_model::data::Species::decode(species_id).to_string())
    };
    let fav_combo = {
       let fav_combo_ids: Option<(i64, i64)> = {
          use crawl_model::db_schema::games::dsl::*;
          use diesel::dsl::count_star;
-         get_query(name_param.as_ref())
+         player
+            .get_query()
             .select((species_id, background_id))
             .group_by((species_id, background_id))
             .order(count_star().desc())
             .first(&*connection)
-            .optional()
-            .expect("Error loading games")
+            .optional()?
       };
-      if let Some((species_id, bg_id)) = fav_combo_ids {
-         let species = unsafe { std::mem::transmute::<i64, crawl_model::data::Species>(species_id) };
-         let background = unsafe { std::mem::transmute::<i64, crawl_model::data::Background>(bg_id) };
-         format!("{:?} {:?}", species, background)
-      } else {
-         "N/A".into()
-      }
+      fav_combo_ids.map(|(species_id, bg_id)| Combo {
+         species: crawl_model::data::Species::decode(species_id).to_string(),
+         background: crawl_model::data::Background::decode(bg_id).to_string(),
+      })
    };
    let fav_god = {
       let fav_god_id: Option<i64> = {
          use crawl_model::db_schema::games::dsl::*;
          use diesel::dsl::count;
-         get_query(name_param.as_ref())
+         player
+            .get_query()
             .filter(god_id.ne(crawl_model::data::God::Atheist as i64))
             .order(count(god_id).desc())
             .select(god_id)
             .group_by(god_id)
             .first(&*connection)
-            .optional()
-            .expect("Error loading games")
+            .optional()?
       };
-      if let Some(god_id) = fav_god_id {
-         let god = unsafe { std::mem::transmute::<i64, crawl_model::data::God>(god_id) };
-         format!("{:?}", god)
-      } else {
-         "N/A".into()
-      }
+      fav_god_id.map(|god_id| crawl_model::data::God::decode(god_id).to_string())
    };
-   let fav_nemesis = {
-      let fav_nemesis: Option<String> = {
-         use crawl_model::db_schema::games::dsl::*;
-         use diesel::dsl::count;
-         get_query(name_param.as_ref())
-            .filter(tmsg.ne("got out of the dungeon alive"))
-            .filter(tmsg.ne("quit the game"))
-            .filter(tmsg.ne("safely got out of the dungeon"))
-            .order(count(tmsg).desc())
-            .select(tmsg)
-            .group_by(tmsg)
-            .first(&*connection)
-            .optional()
-            .expect("Error loading games")
+   let fav_nemesis: Option<String> = {
+      use crawl_model::db_schema::games::dsl::*;
+      use diesel::dsl::count;
+      player
+         .get_query()
+         .filter(tmsg.ne("got out of the dungeon alive"))
+         .filter(tmsg.ne("quit the game"))
+         .filter(tmsg.ne("safely got out of the dungeon"))
+         .order(count(tmsg).desc())
+         .select(tmsg)
+         .group_by(tmsg)
+         .first(&*connection)
+         .optional()?
+   };
+   let fav_death_spot: Option<String> = {
+      use crawl_model::db_schema::games::dsl::*;
+      use diesel::dsl::count;
+      player
+         .get_query()
+         .order(count(place).desc())
+         .select(place)
+         .group_by(place)
+         .first(&*connection)
+         .optional()?
+   };
+   let records = {
+      use crawl_model::db_schema::games::dsl::*;
+      let record = |query: crawl_model::db_schema::games::BoxedQuery<diesel::sqlite::Sqlite>| -> Result<_> {
+         Ok(query
+            .then_order_by(end.asc())
+            .first::<crawl_model::db_model::Game>(&*connection)
+            .optional()?
+            .map(RecordGame::from))
       };
-      if let Some(nemesis) = fav_nemesis {
-         nemesis
-      } else {
-         "N/A".into()
+      let wins = || player.get_query().filter(tmsg.eq("escaped with the Orb"));
+      let deaths = || player.get_query().filter(tmsg.ne_all(NOT_DEATHS.to_vec()));
+      Records {
+         highest_score: record(player.get_query().order(score.desc()))?,
+         fastest_win_turns: record(wins().order(turn.asc()))?,
+         fastest_win_time: record(wins().order(dur.asc()))?,
+         highest_xl_death: record(deaths().order(xl.desc()))?,
+         most_runes: record(player.get_query().order(runes.desc()))?,
+         longest_survival: record(deaths().order(turn.desc()))?,
       }
    };
-   let fav_death_spot = {
-      let fav_death_spot: Option<String> = {
-         use crawl_model::db_schema::games::dsl::*;
-         use diesel::dsl::count;
-         get_query(name_param.as_ref())
-            .order(count(place).desc())
-            .select(place)
-            .group_by(place)
-            .first(&*connection)
-            .optional()
-            .expect("Error loading games")
-      };
-      if let Some(death_spot) = fav_death_spot {
-         death_spot
-      } else {
-         "N/A".into()
+   let (current_streak, best_streak) = match *player {
+      Player::Everyone => (None, None),
+      _ => {
+         let all_streaks = streaks::find_streaks(streaks::load_outcomes(&*connection, player.get_query())?);
+         let current_streak = all_streaks
+            .iter()
+            .filter(|x| x.ongoing)
+            .map(|x| x.games.len() as i64)
+            .max()
+            .unwrap_or(0);
+         let mut best_streak: Option<streaks::Streak> = None;
+         for streak in all_streaks {
+            // Ties go to whoever got there first
+            let is_better = match best_streak {
+               Some(ref best) => {
+                  streak.games.len() > best.games.len()
+                     || (streak.games.len() == best.games.len() && streak.games[0].end < best.games[0].end)
+               }
+               None => true,
+            };
+            if is_better {
+               best_streak = Some(streak);
+            }
+         }
+         let best_streak = summarize_streaks(&*connection, best_streak.into_iter().collect())?.pop();
+         (Some(current_streak), best_streak)
       }
    };
-   UserContext {
+   Ok(UserStats {
       fav_background: fav_bg,
       fav_species: fav_species,
       fav_god: fav_god,
       games: num_games,
       wins: num_wins,
-      winrate: format!("{:.2}", (num_wins as f64 / num_games as f64) * 100.0),
-      name: name_param.unwrap_or_else(|| "Server".into()),
+      winrate: if num_games > 0 {
+         Some(num_wins as f64 / num_games as f64)
+      } else {
+         None
+      },
+      name: match *player {
+         Player::Everyone => None,
+         Player::Account(ref val) | Player::Person(ref val, _) => Some(val.clone()),
+      },
+      accounts: match *player {
+         Player::Everyone => vec![],
+         Player::Account(ref val) => vec![val.clone()],
+         Player::Person(_, ref accounts) => accounts.clone(),
+      },
       nemesis: fav_nemesis,
       death_spot: fav_death_spot,
       num_runes: num_runes,
       fav_combo: fav_combo,
+      records: records,
+      current_streak: current_streak,
+      best_streak: best_streak,
+   })
+}
+
+impl UserContext {
+   fn new(stats: UserStats, player: &Player) -> UserContext {
+      let or_na = |x: Option<String>| x.unwrap_or_else(|| "N/A".into());
+      let games_url = match player.query_param() {
+         Some(param) => format!("/?{}", param),
+         None => "/".into(),
+      };
+      let wins_url = match player.query_param() {
+         Some(param) => format!("/?{}&victory=true", param),
+         None => "/?victory=true".into(),
+      };
+      UserContext {
+         fav_background: or_na(stats.fav_background),
+         fav_species: or_na(stats.fav_species),
+         fav_god: or_na(stats.fav_god),
+         games: stats.games,
+         wins: stats.wins,
+         winrate: format!("{:.2}", stats.winrate.unwrap_or(std::f64::NAN) * 100.0),
+         name: stats.name.unwrap_or_else(|| "Server".into()),
+         accounts: match *player {
+            Player::Person(..) => stats.accounts,
+            _ => vec![],
+         },
+         games_url: games_url,
+         wins_url: wins_url,
+         nemesis: or_na(stats.nemesis),
+         death_spot: or_na(stats.death_spot),
+         num_runes: stats.num_runes,
+         fav_combo: or_na(stats.fav_combo.map(|x| format!("{} {}", x.species, x.background))),
+         records: format_records(stats.records),
+         current_streak: stats.current_streak,
+         best_streak: stats.best_streak,
+      }
    }
 }
 
+fn format_records(records: Records) -> Vec<FormattedRecord> {
+   let all: Vec<(&'static str, Option<RecordGame>, fn(&RecordGame) -> String)> = vec![
+      ("Highest Score", records.highest_score, |x| x.score.to_string()),
+      ("Fastest Win (turns)", records.fastest_win_turns, |x| {
+         format!("{} turns", x.turns)
+      }),
+      ("Fastest Win (realtime)", records.fastest_win_time, |x| {
+         seconds_to_humantime(x.dur)
+      }),
+      ("Highest XL Death", records.highest_xl_death, |x| format!("XL {}", x.xl)),
+      ("Most Runes", records.most_runes, |x| format!("{} runes", x.runes)),
+      ("Longest Survival", records.longest_survival, |x| {
+         format!("{} turns", x.turns)
+      }),
+   ];
+   all.into_iter()
+      .filter_map(|(title, game, value)| {
+         game.map(|game| FormattedRecord {
+            title: title,
+            value: value(&game),
+            game: game,
+         })
+      })
+      .collect()
+}
+
+fn get_user_context(pool: &DatabasePool, player: &Player) -> Result<UserContext> {
+   Ok(UserContext::new(get_user_stats(pool, player)?, player))
+}
+
+/// Looks up `person` in the alias table, or `None` if they have no accounts.
+fn get_person(aliases: &Aliases, person: String) -> Option<Player> {
+   let accounts = aliases.current().accounts(&person)?.to_vec();
+   Some(Player::Person(person, accounts))
+}
+
+/// An account with no games is most likely a typo, so it's not found rather than shown with empty stats.
 #[get("/u/<name_param>")]
-fn user(state: State<DatabasePool>, name_param: String) -> Template {
-   let context = get_user_context(state, Some(name_param));
-   Template::render("user", &context)
+fn user(state: State<DatabasePool>, name_param: String) -> Result<Option<Template>> {
+   let context = get_user_context(&state, &Player::Account(name_param))?;
+   if context.games == 0 {
+      return Ok(None);
+   }
+   Ok(Some(Template::render("user", &context)))
 }
 
-#[get("/everyone")]
-fn everyone(state: State<DatabasePool>) -> Template {
-   let context = get_user_context(state, None);
-   Template::render("user", &context)
+#[get("/p/<person>")]
+fn person(state: State<DatabasePool>, aliases: State<Aliases>, person: String) -> Result<Option<Template>> {
+   let player = match get_person(&aliases, person) {
+      Some(player) => player,
+      None => return Ok(None),
+   };
+   let context = get_user_context(&state, &player)?;
+   Ok(Some(Template::render("user", &context)))
 }
 
-#[get("/deaths")]
-fn deaths(state: State<DatabasePool>) -> Template {
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let deaths: Vec<(String, i64)> = {
-      use crawl_model::db_schema::games::dsl::*;
-      use diesel::dsl::sql;
-      use diesel::sql_types::BigInt;
-      games
-         .select((tmsg, sql::<BigInt>("COUNT(games.tmsg)")))
-         .order(sql::<BigInt>("COUNT(games.tmsg)").desc())
-         .group_by(tmsg)
-         .limit(100)
-         .load::<_>(&*connection)
-         .expect("Error loading games")
+/// Groups each player's games by `dimensions`, keeping only the groups that every player has games in.
+fn get_shared_rows(
+   connection: &SqliteConnection,
+   players: &[Player],
+   names: &[String],
+   dimensions: &[Dimension],
+) -> Result<Vec<SharedRow>> {
+   use std::collections::HashMap;
+   let mut per_player: Vec<HashMap<String, stats::Group>> = Vec::with_capacity(players.len());
+   for player in players {
+      let groups = stats::group_by(connection, player.get_query(), dimensions, None)?;
+      per_player.push(groups.into_iter().map(|x| (x.values.join(" "), x)).collect());
+   }
+   let mut shared: Vec<String> = match per_player.first() {
+      Some(first) => first
+         .keys()
+         .filter(|key| per_player.iter().all(|x| x.contains_key(*key)))
+         .cloned()
+         .collect(),
+      None => vec![],
    };
-   let formatted_items = deaths
+   shared.sort();
+   Ok(shared
       .into_iter()
-      .map(|x| FormattedFreqItem {
-         value: x.0,
-         frequency: x.1,
+      .map(|key| {
+         let groups: Vec<&stats::Group> = per_player.iter().map(|x| &x[&key]).collect();
+         let winrate = |x: &stats::Group| x.wins as f64 / x.games as f64;
+         let leader = groups
+            .iter()
+            .zip(names)
+            .max_by(|a, b| {
+               winrate(a.0)
+                  .partial_cmp(&winrate(b.0))
+                  .unwrap_or(std::cmp::Ordering::Equal)
+                  .then(a.0.best_score.cmp(&b.0.best_score))
+            })
+            .map(|x| x.1.clone())
+            .unwrap_or_default();
+         SharedRow {
+            cells: groups
+               .iter()
+               .map(|x| SharedCell {
+                  games: x.games,
+                  wins: x.wins,
+                  winrate: format!("{:.2}", winrate(x) * 100.0),
+                  best_score: x.best_score,
+               })
+               .collect(),
+            value: key,
+            leader: leader,
+         }
       })
-      .collect();
-   let context = FreqContext {
-      name: "Cause of Death",
-      items: formatted_items,
-   };
-   Template::render("frequency", &context)
+      .collect())
 }
 
-#[get("/places")]
-fn places(state: State<DatabasePool>) -> Template {
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let places: Vec<(String, i64)> = {
-      use crawl_model::db_schema::games::dsl::*;
-      use diesel::dsl::sql;
-      use diesel::sql_types::BigInt;
-      games
-         .select((place, sql::<BigInt>("COUNT(games.place)")))
-         .order(sql::<BigInt>("COUNT(games.place)").desc())
-         .group_by(place)
-         .limit(100)
-         .load::<_>(&*connection)
-         .expect("Error loading games")
-   };
-   let formatted_items = places
-      .into_iter()
-      .map(|x| FormattedFreqItem {
-         value: x.0,
-         frequency: x.1,
+/// Compares two or more accounts side by side, e.g. `/compare/brick/max`.
+#[get("/compare/<names..>")]
+fn compare(state: State<DatabasePool>, names: rocket::http::uri::Segments) -> Result<Option<Template>> {
+   let names: Vec<String> = names.map(String::from).collect();
+   if names.len() < 2 {
+      return Ok(None);
+   }
+   let players: Vec<Player> = names.iter().map(|x| Player::Account(x.clone())).collect();
+   let contexts = players
+      .iter()
+      .map(|x| get_user_context(&state, x))
+      .collect::<Result<Vec<UserContext>>>()?;
+   if contexts.iter().any(|x| x.games == 0) {
+      return Ok(None);
+   }
+   let connection = state.get()?;
+   let sections: [(&'static str, &[Dimension]); 3] = [
+      ("Combos", &[Dimension::Species, Dimension::Background]),
+      ("Species", &[Dimension::Species]),
+      ("Gods", &[Dimension::God]),
+   ];
+   let shared = sections
+      .iter()
+      .map(|&(title, dimensions)| {
+         Ok(SharedSection {
+            title: title,
+            rows: get_shared_rows(&*connection, &players, &names, dimensions)?,
+         })
       })
-      .collect();
-   let context = FreqContext {
-      name: "Final Location",
-      items: formatted_items,
+      .collect::<Result<Vec<SharedSection>>>()?;
+   let context = CompareContext {
+      players: contexts,
+      shared: shared,
    };
-   Template::render("frequency", &context)
+   Ok(Some(Template::render("compare", &context)))
 }
 
-#[get("/species")]
-fn species(state: State<DatabasePool>) -> Template {
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let species: Vec<(i64, i64)> = {
-      use crawl_model::db_schema::games::dsl::*;
-      use diesel::dsl::sql;
-      use diesel::sql_types::BigInt;
-      games
-         .select((species_id, sql::<BigInt>("COUNT(games.species_id)")))
-         .order(sql::<BigInt>("COUNT(games.species_id)").desc())
-         .group_by(species_id)
-         .limit(100)
-         .load::<_>(&*connection)
-         .expect("Error loading games")
+#[get("/everyone")]
+fn everyone(state: State<DatabasePool>) -> Result<Template> {
+   let context = get_user_context(&state, &Player::Everyone)?;
+   Ok(Template::render("user", &context))
+}
+
+#[get("/api/u/<name_param>")]
+fn api_user(state: State<DatabasePool>, name_param: String) -> Result<Option<Json<ApiResponse<UserStats>>>> {
+   let stats = get_user_stats(&state, &Player::Account(name_param))?;
+   if stats.games == 0 {
+      return Ok(None);
+   }
+   Ok(Some(ApiResponse::new(stats)))
+}
+
+#[get("/api/p/<person>")]
+fn api_person(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   person: String,
+) -> Result<Option<Json<ApiResponse<UserStats>>>> {
+   let player = match get_person(&aliases, person) {
+      Some(player) => player,
+      None => return Ok(None),
    };
-   let formatted_items = species
+   Ok(Some(ApiResponse::new(get_user_stats(&state, &player)?)))
+}
+
+#[get("/api/everyone")]
+fn api_everyone(state: State<DatabasePool>) -> Result<Json<ApiResponse<UserStats>>> {
+   Ok(ApiResponse::new(get_user_stats(&state, &Player::Everyone)?))
+}
+
+fn get_freq_context(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   dimensions: &[Dimension],
+   game_query: GameQuery,
+) -> Result<FreqContext> {
+   let aliases = aliases.current();
+   let person_accounts = game_query.person_accounts(&aliases);
+   let connection = state.get()?;
+   let groups = stats::group_by(
+      &*connection,
+      game_query.filtered(person_accounts),
+      dimensions,
+      Some(100),
+   )?;
+   Ok(FreqContext {
+      columns: dimensions.iter().map(|x| x.title()).collect(),
+      items: groups.into_iter().map(|x| x.into()).collect(),
+      filters: game_query.active_filters(),
+      show_outcomes: dimensions.iter().all(|x| x.has_outcomes()),
+   })
+}
+
+/// Games grouped by one or more dimensions, e.g. `/stats/species,god`.
+#[get("/stats/<group>")]
+fn group_stats(state: State<DatabasePool>, aliases: State<Aliases>, group: DimensionList) -> Result<Template> {
+   group_stats_query(state, aliases, group, GameQuery::default())
+}
+
+#[get("/stats/<group>?<game_query>")]
+fn group_stats_query(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   group: DimensionList,
+   game_query: GameQuery,
+) -> Result<Template> {
+   let context = get_freq_context(state, aliases, &group.0, game_query)?;
+   Ok(Template::render("frequency", &context))
+}
+
+#[get("/players")]
+fn players(state: State<DatabasePool>, aliases: State<Aliases>, uri: &URI) -> Result<Template> {
+   players_query(
+      state,
+      aliases,
+      uri,
+      PlayerQuery {
+         sort_by: None,
+         order: None,
+      },
+   )
+}
+
+#[get("/players?<player_query>")]
+fn players_query(
+   state: State<DatabasePool>,
+   aliases: State<Aliases>,
+   uri: &URI,
+   player_query: PlayerQuery,
+) -> Result<Template> {
+   let connection = state.get()?;
+   let mut groups = stats::group_by(
+      &*connection,
+      crawl_model::db_schema::games::table.into_boxed(),
+      &[Dimension::Name],
+      None,
+   )?;
+   let column = player_query.sort_by.unwrap_or(PlayerColumn::Games);
+   let order = player_query.order.unwrap_or_else(|| column.default_order());
+   let winrate = |x: &stats::Group| x.wins as f64 / x.games as f64;
+   // Sort by name first so that ties are broken alphabetically
+   groups.sort_by(|a, b| a.values[0].cmp(&b.values[0]));
+   groups.sort_by(|a, b| {
+      let ordering = match column {
+         PlayerColumn::Name => a.values[0].cmp(&b.values[0]),
+         PlayerColumn::Games => a.games.cmp(&b.games),
+         PlayerColumn::Wins => a.wins.cmp(&b.wins),
+         PlayerColumn::Winrate => winrate(a).partial_cmp(&winrate(b)).unwrap_or(std::cmp::Ordering::Equal),
+         PlayerColumn::Runes => a.total_runes.cmp(&b.total_runes),
+         PlayerColumn::BestScore => a.best_score.cmp(&b.best_score),
+         PlayerColumn::FastestWin => a.fastest_win.cmp(&b.fastest_win),
+         PlayerColumn::LastPlayed => a.last_played.cmp(&b.last_played),
+      };
+      match order {
+         SortOrder::Asc => ordering,
+         SortOrder::Desc => ordering.reverse(),
+      }
+   });
+   if column == PlayerColumn::FastestWin {
+      // Players without a win have no fastest win, and belong at the bottom either way
+      groups.sort_by_key(|x| x.fastest_win.is_none());
+   }
+   let aliases = aliases.current();
+   let players = groups
       .into_iter()
-      .map(|x| FormattedFreqItem {
-         value: format!("{:?}", unsafe {
-            std::mem::transmute::<i64, crawl_model::data::Species>(x.0)
-         }),
-         frequency: x.1,
+      .map(|x| {
+         let name = x.values.into_iter().next().unwrap_or_default();
+         FormattedPlayer {
+            real_name: aliases.real_name(&name).into(),
+            name: name,
+            games: x.games,
+            wins: x.wins,
+            winrate: format!("{:.2}", (x.wins as f64 / x.games as f64) * 100.0),
+            total_runes: x.total_runes,
+            best_score: x.best_score,
+            fastest_win: x.fastest_win,
+            last_played: dates::format_timestamp(x.last_played),
+         }
       })
       .collect();
-   let context = FreqContext {
-      name: "Species",
-      items: formatted_items,
+   let context = PlayersContext {
+      players: players,
+      sort: SortLinks::new(uri, column, order),
    };
-   Template::render("frequency", &context)
+   Ok(Template::render("players", &context))
 }
 
-#[get("/backgrounds")]
-fn backgrounds(state: State<DatabasePool>) -> Template {
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let backgrounds: Vec<(i64, i64)> = {
-      use crawl_model::db_schema::games::dsl::*;
-      use diesel::dsl::sql;
-      use diesel::sql_types::BigInt;
-      games
-         .select((background_id, sql::<BigInt>("COUNT(games.background_id)")))
-         .order(sql::<BigInt>("COUNT(games.background_id)").desc())
-         .group_by(background_id)
-         .limit(100)
-         .load::<_>(&*connection)
-         .expect("Error loading games")
-   };
-   let formatted_items = backgrounds
+/// How many streaks the `/streaks` leaderboard shows.
+const STREAK_LEADERBOARD_SIZE: usize = 100;
+
+/// The longest win streaks ever, across every account.
+#[get("/streaks")]
+fn streak_leaderboard(state: State<DatabasePool>) -> Result<Template> {
+   let connection = state.get()?;
+   let outcomes = streaks::load_outcomes(&*connection, crawl_model::db_schema::games::table.into_boxed())?;
+   let mut all_streaks: Vec<streaks::Streak> = streaks::find_streaks(outcomes)
       .into_iter()
-      .map(|x| FormattedFreqItem {
-         value: format!("{:?}", unsafe {
-            std::mem::transmute::<i64, crawl_model::data::Background>(x.0)
-         }),
-         frequency: x.1,
-      })
+      .filter(|x| x.games.len() >= 2)
       .collect();
-   let context = FreqContext {
-      name: "Background",
-      items: formatted_items,
+   // Longest first, with ties going to whoever got there first
+   all_streaks.sort_by_key(|x| (std::cmp::Reverse(x.games.len()), x.games[0].end));
+   all_streaks.truncate(STREAK_LEADERBOARD_SIZE);
+   let context = StreaksContext {
+      streaks: summarize_streaks(&*connection, all_streaks)?,
    };
-   Template::render("frequency", &context)
+   Ok(Template::render("streaks", &context))
 }
 
-#[get("/gods")]
-fn gods(state: State<DatabasePool>) -> Template {
-   let connection = state.get().expect("Timeout waiting for pooled connection");
-   let gods: Vec<(i64, i64)> = {
-      use crawl_model::db_schema::games::dsl::*;
-      use diesel::dsl::sql;
-      use diesel::sql_types::BigInt;
-      games
-         .select((god_id, sql::<BigInt>("COUNT(games.god_id)")))
-         .order(sql::<BigInt>("COUNT(games.god_id)").desc())
-         .group_by(god_id)
-         .limit(100)
-         .load::<_>(&*connection)
-         .expect("Error loading games")
-   };
-   let formatted_items = gods
+#[get("/combos")]
+fn combos(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   combos_query(state, aliases, GameQuery::default())
+}
+
+#[get("/combos?<game_query>")]
+fn combos_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   use std::collections::{BTreeMap, BTreeSet};
+   let aliases = aliases.current();
+   let person_accounts = game_query.person_accounts(&aliases);
+   let connection = state.get()?;
+   let groups = stats::group_by(
+      &*connection,
+      game_query.filtered(person_accounts),
+      &[Dimension::Species, Dimension::Background],
+      None,
+   )?;
+   let mut species = BTreeSet::new();
+   let mut backgrounds = BTreeSet::new();
+   let mut cells = BTreeMap::new();
+   for group in groups {
+      let mut values = group.values.into_iter();
+      let (sp, bg) = match (values.next(), values.next()) {
+         (Some(sp), Some(bg)) => (sp, bg),
+         _ => continue,
+      };
+      species.insert(sp.clone());
+      backgrounds.insert(bg.clone());
+      cells.insert((sp, bg), (group.games, group.wins));
+   }
+   let rows = species
       .into_iter()
-      .map(|x| FormattedFreqItem {
-         value: format!("{:?}", unsafe {
-            std::mem::transmute::<i64, crawl_model::data::God>(x.0)
-         }),
-         frequency: x.1,
+      .map(|sp| {
+         let row_cells = backgrounds
+            .iter()
+            .map(|bg| {
+               let (games, wins) = cells.get(&(sp.clone(), bg.clone())).cloned().unwrap_or((0, 0));
+               let class = if wins > 0 {
+                  "combo-won"
+               } else if games > 0 {
+                  "combo-played"
+               } else {
+                  "combo-unplayed"
+               };
+               ComboCell {
+                  games: games,
+                  wins: wins,
+                  class: class,
+               }
+            })
+            .collect();
+         ComboRow {
+            species: sp,
+            cells: row_cells,
+         }
       })
       .collect();
-   let context = FreqContext {
-      name: "God",
-      items: formatted_items,
+   let context = ComboContext {
+      backgrounds: backgrounds.into_iter().collect(),
+      rows: rows,
+      filters: game_query.active_filters(),
    };
-   Template::render("frequency", &context)
+   Ok(Template::render("combos", &context))
+}
+
+#[get("/deaths")]
+fn deaths(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   deaths_query(state, aliases, GameQuery::default())
+}
+
+#[get("/deaths?<game_query>")]
+fn deaths_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   group_stats_query(state, aliases, DimensionList(vec![Dimension::Death]), game_query)
+}
+
+#[get("/places")]
+fn places(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   places_query(state, aliases, GameQuery::default())
+}
+
+#[get("/places?<game_query>")]
+fn places_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   group_stats_query(state, aliases, DimensionList(vec![Dimension::Place]), game_query)
+}
+
+#[get("/species")]
+fn species(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   species_query(state, aliases, GameQuery::default())
+}
+
+#[get("/species?<game_query>")]
+fn species_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   group_stats_query(state, aliases, DimensionList(vec![Dimension::Species]), game_query)
+}
+
+#[get("/backgrounds")]
+fn backgrounds(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   backgrounds_query(state, aliases, GameQuery::default())
+}
+
+#[get("/backgrounds?<game_query>")]
+fn backgrounds_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   group_stats_query(state, aliases, DimensionList(vec![Dimension::Background]), game_query)
+}
+
+#[get("/gods")]
+fn gods(state: State<DatabasePool>, aliases: State<Aliases>) -> Result<Template> {
+   gods_query(state, aliases, GameQuery::default())
+}
+
+#[get("/gods?<game_query>")]
+fn gods_query(state: State<DatabasePool>, aliases: State<Aliases>, game_query: GameQuery) -> Result<Template> {
+   group_stats_query(state, aliases, DimensionList(vec![Dimension::God]), game_query)
 }
 
 #[get("/<file..>", rank = 4)]
@@ -657,23 +2040,165 @@ fn main() {
    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
    let manager = r2d2_diesel::ConnectionManager::<SqliteConnection>::new(database_url);
    let pool = r2d2::Pool::new(manager).expect("Failed to create pool.");
-   rocket::ignite()
+   let rocket = rocket::ignite();
+   let aliases_path = std::env::var("ALIASES_PATH").unwrap_or_else(|_| "aliases.toml".into());
+   let aliases = Aliases::load(aliases_path.into());
+   let base_url = feed::BaseUrl::from_env();
+   rocket
       .mount(
          "/",
          routes![
             hiscores,
             files,
             deaths,
+            deaths_query,
             hi_query,
             species,
+            species_query,
             backgrounds,
+            backgrounds_query,
             gods,
+            gods_query,
             user,
+            person,
+            game,
+            atom_feed,
+            live,
+            atom_feed_query,
+            compare,
             places,
-            everyone
+            places_query,
+            everyone,
+            group_stats,
+            group_stats_query,
+            combos,
+            combos_query,
+            players,
+            players_query,
+            streak_leaderboard,
+            api_games,
+            api_games_query,
+            api_user,
+            api_person,
+            api_everyone
          ],
       )
+      .catch(errors![
+         error::not_found,
+         error::unprocessable_entity,
+         error::internal_error
+      ])
       .manage(pool)
+      .manage(aliases)
+      .manage(base_url)
       .attach(Template::fairing())
       .launch();
 }
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crawl_model::data::God;
+   use rocket::http::RawStr;
+   use rocket::request::{FormItems, FromForm, FromFormValue};
+
+   fn parse_query(query: &str) -> std::result::Result<GameQuery, ()> {
+      GameQuery::from_form(&mut FormItems::from(query), true)
+   }
+
+   fn parse_gods(value: &str) -> std::result::Result<FilterList<God>, ()> {
+      FilterList::from_form_value(RawStr::from_str(value))
+   }
+
+   #[test]
+   fn filter_lists_split_on_commas() {
+      let list = parse_gods("Trog, !Xom,Okawaru").unwrap();
+      assert_eq!(list.include_ids(), vec![God::Trog.id(), God::Okawaru.id()]);
+      assert_eq!(list.exclude_ids(), vec![God::Xom.id()]);
+      assert!(parse_gods("Trog,Nobody").is_err());
+      assert!(parse_gods("!").is_err());
+   }
+
+   #[test]
+   fn repeated_list_keys_are_merged() {
+      let query = parse_query("god=Trog&god=!Xom&god=Okawaru").unwrap();
+      let god = query.god.unwrap();
+      assert_eq!(god.include_ids(), vec![God::Trog.id(), God::Okawaru.id()]);
+      assert_eq!(god.exclude_ids(), vec![God::Xom.id()]);
+   }
+
+   #[test]
+   fn repeated_single_keys_fail_the_query() {
+      assert!(parse_query("min_xl=1&min_xl=2").is_err());
+      assert!(parse_query("name=a&name=b").is_err());
+   }
+
+   #[test]
+   fn unknown_keys_fail_only_strict_queries() {
+      assert!(parse_query("colour=red").is_err());
+      assert!(GameQuery::from_form(&mut FormItems::from("colour=red"), false).is_ok());
+   }
+
+   #[test]
+   fn bad_values_fail_the_query() {
+      assert!(parse_query("god=Nobody").is_err());
+      assert!(parse_query("min_xl=abc").is_err());
+      assert!(parse_query("victory=maybe").is_err());
+   }
+
+   #[test]
+   fn blank_values_are_ignored() {
+      let query = parse_query("god=&min_xl=&min_xl=3").unwrap();
+      assert!(query.god.is_none());
+      assert_eq!(query.min_xl, Some(3));
+   }
+
+   #[test]
+   fn query_params_are_replaced() {
+      let uri = URI::new("/?god=Trog&page=2");
+      assert_eq!(with_query_params(&uri, &[("page", Some("3"))]), "/?god=Trog&page=3");
+      assert_eq!(with_query_params(&uri, &[("page", None)]), "/?god=Trog");
+      assert_eq!(
+         with_query_params(&URI::new("/"), &[("sort_by", Some("xl"))]),
+         "/?sort_by=xl"
+      );
+   }
+
+   #[test]
+   fn feed_ids_ignore_order_and_presentation() {
+      let base_url = feed::BaseUrl("http://example.com".into());
+      assert_eq!(feed::feed_id(&base_url, None), "http://example.com/feed.atom");
+      assert_eq!(
+         feed::feed_id(&base_url, Some("page=2&per_page=10")),
+         "http://example.com/feed.atom"
+      );
+      assert_eq!(
+         feed::feed_id(&base_url, Some("name=a&sort_by=xl&god=Trog")),
+         feed::feed_id(&base_url, Some("god=Trog&name=a"))
+      );
+      assert_ne!(
+         feed::feed_id(&base_url, Some("god=Trog")),
+         feed::feed_id(&base_url, Some("god=Xom"))
+      );
+   }
+
+   #[test]
+   fn dates_filter_games() {
+      let query = parse_query("since=2018-06-01&until=7d").unwrap();
+      assert!(query.since.is_some());
+      assert!(query.until.is_some());
+   }
+
+   #[test]
+   fn bad_dates_fail_the_query() {
+      assert!(parse_query("since=yesterday").is_err());
+      assert!(parse_query("until=100000000d").is_err());
+   }
+
+   #[test]
+   fn huge_pages_dont_overflow() {
+      let query = parse_query("page=9223372036854775807&per_page=1000").unwrap();
+      assert_eq!(query.offset(), i64::max_value());
+      assert!(query.offset().saturating_add(query.per_page()) >= 0);
+   }
+}
@@ -0,0 +1,113 @@
+//! Reading of the `milestones` table filled in by `crawl_score_import`.
+//!
+//! A game's milestones share its player name and start time. The `games` table doesn't record start times, so a
+//! game is taken to have finished once its player has a game in `games` that ended after it started.
+//!
+//! The table only exists once milestones have been imported. Until then every game simply has no milestones.
+
+use crate::schema::milestones;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+#[derive(Queryable)]
+pub struct Milestone {
+   pub id: i64,
+   pub name: String,
+   pub start: i64,
+   pub species_id: i64,
+   pub background_id: i64,
+   pub god_id: i64,
+   pub xl: i64,
+   pub place: String,
+   pub turn: i64,
+   pub runes: i64,
+   pub time: i64,
+   pub kind: String,
+   pub message: String,
+}
+
+fn table_exists(connection: &SqliteConnection) -> QueryResult<bool> {
+   use diesel::dsl::sql;
+   use diesel::sql_types::Bool;
+   diesel::select(sql::<Bool>(
+      "EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'milestones')",
+   ))
+   .get_result(connection)
+}
+
+/// The latest milestone of every game that hasn't finished yet and has had a milestone since `since`, most recent
+/// first. Games without a recent milestone have most likely been abandoned.
+pub fn in_progress(connection: &SqliteConnection, since: i64) -> QueryResult<Vec<Milestone>> {
+   use diesel::dsl::sql;
+   use diesel::sql_types::Bool;
+   if !table_exists(connection)? {
+      return Ok(vec![]);
+   }
+   milestones::table
+      .filter(milestones::time.ge(since))
+      .filter(sql::<Bool>(
+         "milestones.id = (SELECT latest.id FROM milestones latest \
+          WHERE latest.name = milestones.name AND latest.start = milestones.start \
+          ORDER BY latest.time DESC, latest.id DESC LIMIT 1) \
+          AND NOT EXISTS (SELECT 1 FROM games \
+          WHERE games.name = milestones.name AND games.\"end\" >= milestones.start)",
+      ))
+      .order(milestones::time.desc())
+      .load(connection)
+}
+
+/// Every milestone of the game `name` finished at `end`, in the order they happened. That's the game with the
+/// latest start time no later than `end`, as long as none of the player's other games ended in between. Otherwise
+/// this game's milestones were never imported, and it has none.
+pub fn for_game(connection: &SqliteConnection, name: &str, end: i64) -> QueryResult<Vec<Milestone>> {
+   use diesel::dsl::sql;
+   use diesel::sql_types::Bool;
+   if !table_exists(connection)? {
+      return Ok(vec![]);
+   }
+   milestones::table
+      .filter(milestones::name.eq(name))
+      .filter(sql::<Bool>(&format!(
+         "milestones.start = (SELECT MAX(earlier.start) FROM milestones earlier \
+          WHERE earlier.name = milestones.name AND earlier.start <= {end} \
+          AND NOT EXISTS (SELECT 1 FROM games \
+          WHERE games.name = earlier.name AND games.\"end\" >= earlier.start AND games.\"end\" < {end}))",
+         end = end
+      )))
+      .order((milestones::time.asc(), milestones::id.asc()))
+      .load(connection)
+}
+
+#[derive(Serialize)]
+pub struct RuneFound {
+   /// e.g. `slimy`.
+   pub rune: String,
+   pub turn: i64,
+   pub place: String,
+}
+
+/// Every rune picked up during a game, in order, from its milestones.
+pub fn runes_found(milestones: &[Milestone]) -> Vec<RuneFound> {
+   milestones
+      .iter()
+      .filter(|x| x.kind == "rune")
+      .map(|x| {
+         // The message looks like `found a slimy rune of Zot.`
+         let rune = x
+            .message
+            .trim_start_matches("found an ")
+            .trim_start_matches("found a ")
+            .trim_end_matches(" rune of Zot.");
+         RuneFound {
+            rune: rune.to_string(),
+            turn: x.turn,
+            place: x.place.clone(),
+         }
+      })
+      .collect()
+}
+
+/// The milestone for the first god worshipped during a game, if any.
+pub fn god_joined(milestones: &[Milestone]) -> Option<&Milestone> {
+   milestones.iter().find(|x| x.kind == "god.worship")
+}
@@ -0,0 +1,21 @@
+//! Tables filled in by `crawl_score_import` that aren't part of `crawl_model`.
+
+// One row per `milestones` line. `start` is when the game began, which together with `name` identifies the game,
+// `time` is when the milestone happened, and `kind` is the DCSS milestone type, e.g. `rune` or `br.enter`.
+table! {
+   milestones (id) {
+      id -> BigInt,
+      name -> Text,
+      start -> BigInt,
+      species_id -> BigInt,
+      background_id -> BigInt,
+      god_id -> BigInt,
+      xl -> BigInt,
+      place -> Text,
+      turn -> BigInt,
+      runes -> BigInt,
+      time -> BigInt,
+      kind -> Text,
+      message -> Text,
+   }
+}
@@ -0,0 +1,220 @@
+//! Generic `GROUP BY` aggregation over the `games` table.
+//!
+//! Games can be grouped by any combination of `Dimension`s, and each group reports how many games it holds
+//! along with how those games turned out.
+
+use crate::decode::DecodeId;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
+use rocket::http::RawStr;
+use rocket::request::FromParam;
+
+/// Separates the values of each dimension in the combined group key.
+const KEY_SEPARATOR: char = '\u{1f}';
+
+/// Aggregates giving, per group: games played, wins, average XL, average runes, total runes, best score, fewest
+/// turns taken to win, and when the last game ended.
+const OUTCOME_COLUMNS: &str = "COUNT(*), \
+   SUM(CASE WHEN games.tmsg = 'escaped with the Orb' THEN 1 ELSE 0 END), \
+   AVG(games.xl), \
+   AVG(games.runes), \
+   SUM(games.runes), \
+   MAX(games.score), \
+   MIN(CASE WHEN games.tmsg = 'escaped with the Orb' THEN games.turn END), \
+   MAX(games.\"end\")";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dimension {
+   Species,
+   Background,
+   God,
+   Xl,
+   Runes,
+   /// The full final location, e.g. `D:5`.
+   Place,
+   /// The branch of the final location, e.g. `D`.
+   Branch,
+   /// The cause of death (or escape).
+   Death,
+   Name,
+}
+
+impl Dimension {
+   pub const ALL: [Dimension; 9] = [
+      Dimension::Species,
+      Dimension::Background,
+      Dimension::God,
+      Dimension::Xl,
+      Dimension::Runes,
+      Dimension::Place,
+      Dimension::Branch,
+      Dimension::Death,
+      Dimension::Name,
+   ];
+
+   pub fn key(self) -> &'static str {
+      match self {
+         Dimension::Species => "species",
+         Dimension::Background => "background",
+         Dimension::God => "god",
+         Dimension::Xl => "xl",
+         Dimension::Runes => "runes",
+         Dimension::Place => "place",
+         Dimension::Branch => "branch",
+         Dimension::Death => "death",
+         Dimension::Name => "name",
+      }
+   }
+
+   pub fn title(self) -> &'static str {
+      match self {
+         Dimension::Species => "Species",
+         Dimension::Background => "Background",
+         Dimension::God => "God",
+         Dimension::Xl => "XL",
+         Dimension::Runes => "Runes",
+         Dimension::Place => "Final Location",
+         Dimension::Branch => "Final Branch",
+         Dimension::Death => "Cause of Death",
+         Dimension::Name => "Name",
+      }
+   }
+
+   /// Whether wins, win rate and the other outcome columns mean anything for groups of this dimension. They don't
+   /// for where or how games ended, since that is the outcome.
+   pub fn has_outcomes(self) -> bool {
+      match self {
+         Dimension::Place | Dimension::Branch | Dimension::Death => false,
+         _ => true,
+      }
+   }
+
+   fn from_key(key: &str) -> Option<Dimension> {
+      match key {
+         "killer" | "deaths" => Some(Dimension::Death),
+         "places" => Some(Dimension::Place),
+         "backgrounds" => Some(Dimension::Background),
+         "gods" => Some(Dimension::God),
+         _ => Dimension::ALL.iter().find(|x| x.key() == key).cloned(),
+      }
+   }
+
+   fn sql(self) -> &'static str {
+      match self {
+         Dimension::Species => "games.species_id",
+         Dimension::Background => "games.background_id",
+         Dimension::God => "games.god_id",
+         Dimension::Xl => "games.xl",
+         Dimension::Runes => "games.runes",
+         Dimension::Place => "games.place",
+         Dimension::Branch => {
+            "CASE WHEN instr(games.place, ':') > 0 \
+             THEN substr(games.place, 1, instr(games.place, ':') - 1) \
+             ELSE games.place END"
+         }
+         Dimension::Death => "games.tmsg",
+         Dimension::Name => "games.name",
+      }
+   }
+
+   /// Turns a raw group value back into something readable.
+   fn format(self, raw: &str) -> String {
+      let decode_id = |f: fn(i64) -> String| raw.parse::<i64>().map(f).unwrap_or_else(|_| raw.into());
+      match self {
+         Dimension::Species => decode_id(|x| crawl_model::data::Species::decode(x).to_string()),
+         Dimension::Background => decode_id(|x| crawl_model::data::Background::decode(x).to_string()),
+         Dimension::God => decode_id(|x| crawl_model::data::God::decode(x).to_string()),
+         _ => raw.into(),
+      }
+   }
+}
+
+/// One or more dimensions to group by, given in a path segment as e.g. `species,god`.
+pub struct DimensionList(pub Vec<Dimension>);
+
+impl<'a> FromParam<'a> for DimensionList {
+   type Error = &'a RawStr;
+
+   fn from_param(param: &'a RawStr) -> Result<DimensionList, &'a RawStr> {
+      let decoded = param.percent_decode().map_err(|_| param)?;
+      let dimensions = decoded
+         .split(',')
+         .map(|x| Dimension::from_key(&x.trim().to_ascii_lowercase()))
+         .collect::<Option<Vec<Dimension>>>()
+         .ok_or(param)?;
+      if dimensions.is_empty() {
+         return Err(param);
+      }
+      Ok(DimensionList(dimensions))
+   }
+}
+
+pub struct Group {
+   /// The formatted value of each dimension, in the order they were requested.
+   pub values: Vec<String>,
+   pub games: i64,
+   pub wins: i64,
+   pub avg_xl: f64,
+   pub avg_runes: f64,
+   pub total_runes: i64,
+   pub best_score: i64,
+   /// Turn count of the quickest win, if there were any wins.
+   pub fastest_win: Option<i64>,
+   /// End time of the most recent game.
+   pub last_played: i64,
+}
+
+/// Groups the games selected by `query` by `dimensions`, returning the `limit` largest groups, or every group if
+/// there is no limit.
+pub fn group_by(
+   connection: &SqliteConnection,
+   query: crawl_model::db_schema::games::BoxedQuery<diesel::sqlite::Sqlite>,
+   dimensions: &[Dimension],
+   limit: Option<i64>,
+) -> QueryResult<Vec<Group>> {
+   use diesel::dsl::sql;
+   let key_sql = dimensions
+      .iter()
+      .map(|x| format!("CAST({} AS TEXT)", x.sql()))
+      .collect::<Vec<_>>()
+      .join(&format!(" || char({}) || ", KEY_SEPARATOR as u32));
+   let group_sql = dimensions.iter().map(|x| x.sql()).collect::<Vec<_>>().join(", ");
+   let mut query = query
+      .select(sql::<(
+         Text,
+         BigInt,
+         BigInt,
+         Double,
+         Double,
+         BigInt,
+         BigInt,
+         Nullable<BigInt>,
+         BigInt,
+      )>(&format!("{}, {}", key_sql, OUTCOME_COLUMNS)))
+      .group_by(sql::<Text>(&group_sql))
+      .order(sql::<BigInt>("COUNT(*)").desc());
+   if let Some(limit) = limit {
+      query = query.limit(limit);
+   }
+   let rows: Vec<(String, i64, i64, f64, f64, i64, i64, Option<i64>, i64)> = query.load(connection)?;
+   Ok(rows
+      .into_iter()
+      .map(
+         |(key, games, wins, avg_xl, avg_runes, total_runes, best_score, fastest_win, last_played)| Group {
+            values: key
+               .split(KEY_SEPARATOR)
+               .zip(dimensions)
+               .map(|(raw, dimension)| dimension.format(raw))
+               .collect(),
+            games: games,
+            wins: wins,
+            avg_xl: avg_xl,
+            avg_runes: avg_runes,
+            total_runes: total_runes,
+            best_score: best_score,
+            fastest_win: fastest_win,
+            last_played: last_played,
+         },
+      )
+      .collect())
+}
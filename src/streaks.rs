@@ -0,0 +1,139 @@
+//! Detection of win streaks: runs of consecutive wins by a single account, ordered by game end time.
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+/// The little of each game that streak detection needs, so that whole rows don't have to be loaded.
+pub struct GameOutcome {
+   pub id: i64,
+   pub name: String,
+   pub end: i64,
+   pub victory: bool,
+}
+
+/// The outcome of every game selected by `query`, ordered by account name and then end time, as `find_streaks`
+/// expects.
+pub fn load_outcomes(
+   connection: &SqliteConnection,
+   query: crawl_model::db_schema::games::BoxedQuery<diesel::sqlite::Sqlite>,
+) -> QueryResult<Vec<GameOutcome>> {
+   use crawl_model::db_schema::games::dsl::*;
+   let rows: Vec<(i64, String, i64, String)> = query
+      .select((id, name, end, tmsg))
+      .order((name.asc(), end.asc(), id.asc()))
+      .load(connection)?;
+   Ok(rows
+      .into_iter()
+      .map(|(game_id, game_name, game_end, game_tmsg)| GameOutcome {
+         id: game_id,
+         name: game_name,
+         end: game_end,
+         victory: game_tmsg == "escaped with the Orb",
+      })
+      .collect())
+}
+
+pub struct Streak {
+   pub name: String,
+   /// The winning games, oldest first.
+   pub games: Vec<GameOutcome>,
+   /// Whether the account hasn't played a losing game since, so the streak could still grow.
+   pub ongoing: bool,
+}
+
+/// Every run of one or more consecutive wins in `games`, which must be ordered by account name and then end time.
+pub fn find_streaks(games: Vec<GameOutcome>) -> Vec<Streak> {
+   let mut streaks: Vec<Streak> = vec![];
+   let mut current: Option<Streak> = None;
+   for game in games {
+      if current.as_ref().map(|x| x.name != game.name).unwrap_or(false) {
+         // That was the previous account's last game, so their streak is still going
+         let mut streak = current.take().unwrap();
+         streak.ongoing = true;
+         streaks.push(streak);
+      }
+      if game.victory {
+         current
+            .get_or_insert_with(|| Streak {
+               name: game.name.clone(),
+               games: vec![],
+               ongoing: false,
+            })
+            .games
+            .push(game);
+      } else {
+         streaks.extend(current.take());
+      }
+   }
+   if let Some(mut streak) = current {
+      streak.ongoing = true;
+      streaks.push(streak);
+   }
+   streaks
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn game(id: i64, name: &str, victory: bool) -> GameOutcome {
+      GameOutcome {
+         id: id,
+         name: name.into(),
+         end: id,
+         victory: victory,
+      }
+   }
+
+   fn summary(streaks: &[Streak]) -> Vec<(&str, Vec<i64>, bool)> {
+      streaks
+         .iter()
+         .map(|x| (x.name.as_str(), x.games.iter().map(|x| x.id).collect(), x.ongoing))
+         .collect()
+   }
+
+   #[test]
+   fn a_loss_ends_the_streak() {
+      let games = vec![
+         game(1, "brick", true),
+         game(2, "brick", true),
+         game(3, "brick", false),
+         game(4, "brick", true),
+         game(5, "brick", false),
+      ];
+      assert_eq!(
+         summary(&find_streaks(games)),
+         vec![("brick", vec![1, 2], false), ("brick", vec![4], false)]
+      );
+   }
+
+   #[test]
+   fn wins_at_the_end_are_ongoing() {
+      let games = vec![game(1, "brick", false), game(2, "brick", true), game(3, "brick", true)];
+      assert_eq!(summary(&find_streaks(games)), vec![("brick", vec![2, 3], true)]);
+   }
+
+   #[test]
+   fn streaks_dont_cross_accounts() {
+      let games = vec![
+         game(1, "brick", true),
+         game(2, "max", true),
+         game(3, "max", false),
+         game(4, "zed", true),
+      ];
+      assert_eq!(
+         summary(&find_streaks(games)),
+         vec![
+            ("brick", vec![1], true),
+            ("max", vec![2], false),
+            ("zed", vec![4], true)
+         ]
+      );
+   }
+
+   #[test]
+   fn no_wins_no_streaks() {
+      assert!(find_streaks(vec![game(1, "brick", false)]).is_empty());
+      assert!(find_streaks(vec![]).is_empty());
+   }
+}